@@ -0,0 +1,34 @@
+use std::ops::{Deref, DerefMut};
+
+// a fixed-size byte buffer whose first byte sits at a multiple of `align`,
+// as direct I/O requires of the memory it transfers to and from the device.
+// It over-allocates a Vec and starts at the first aligned byte, so no unsafe code is needed.
+pub struct AlignedBuf {
+    buf: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    // align must be a power of two
+    pub fn new(len: usize, align: usize) -> AlignedBuf {
+        let buf = vec![0; len + align - 1];
+        let start = buf.as_ptr().align_offset(align);
+
+        AlignedBuf { buf, start, len }
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[self.start..self.start + self.len]
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[self.start..self.start + self.len]
+    }
+}
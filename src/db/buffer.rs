@@ -3,7 +3,6 @@ use super::filemanager::FileMgr;
 use super::logmanager::LogMgr;
 use super::page::Page;
 
-use std::cell::RefCell;
 use std::fmt;
 use std::sync::Arc;
 
@@ -26,23 +25,23 @@ impl fmt::Display for BufferError {
 }
 
 pub struct Buffer {
-    fm: Arc<RefCell<FileMgr>>,
-    lm: Arc<RefCell<LogMgr>>,
+    fm: Arc<FileMgr>,
+    lm: Arc<LogMgr>,
     contents: Page,
     blk: Option<BlockId>, // reference to the block assigned to its page
     pins: u64,            // the number of times the page is pinned
     txnum: i32, // an integer indicating if the page has been modified. The integer indentifies the transaction that make the change
-    lsn: i32, // log information. if the page has been modified, the buffer holds the LSN of the most recent log record.
+    lsn: i64, // log information. if the page has been modified, the buffer holds the LSN of the most recent log record.
 }
 
 impl Buffer {
-    pub fn new(fm: Arc<RefCell<FileMgr>>, lm: Arc<RefCell<LogMgr>>) -> Buffer {
-        let blksize = fm.borrow().blocksize() as usize;
+    pub fn new(fm: Arc<FileMgr>, lm: Arc<LogMgr>) -> Buffer {
+        let contents = fm.new_page();
 
         Buffer {
             fm,
             lm,
-            contents: Page::new_from_size(blksize),
+            contents,
             blk: None,
             pins: 0,
             txnum: -1,
@@ -58,7 +57,8 @@ impl Buffer {
         self.blk.as_ref()
     }
 
-    pub fn set_modified(&mut self, txnum: i32, lsn: i32) {
+    // a negative lsn means the change was not logged
+    pub fn set_modified(&mut self, txnum: i32, lsn: i64) {
         self.txnum = txnum;
         if lsn >= 0 {
             self.lsn = lsn;
@@ -76,7 +76,7 @@ impl Buffer {
     // associate the buffer with the specific block, reading its content from disk
     pub fn assign_to_block(&mut self, b: BlockId) -> Result<()> {
         self.flush()?;
-        self.fm.borrow_mut().read(&b, &mut self.contents)?;
+        self.fm.read(&b, &mut self.contents)?;
         self.blk = Some(b);
         self.pins = 0;
 
@@ -84,13 +84,16 @@ impl Buffer {
     }
 
     // ensure the buffer's assigned disk block has the same values as its page
+    // the log is forced first (write-ahead), each write is synced as the FileMgr's durability policy says
     pub fn flush(&mut self) -> Result<()> {
         // page has been changed
         if self.txnum >= 0 {
-            self.lm.borrow_mut().flush_from_lsn(self.lsn as u64)?;
+            if self.lsn >= 0 {
+                self.lm.flush_from_lsn(self.lsn as u64)?;
+            }
 
             if let Some(br) = self.blk.as_ref() {
-                self.fm.borrow_mut().write(br, &mut self.contents)?;
+                self.fm.write(br, &mut self.contents)?;
                 // need not flush again
                 self.txnum = -1;
             } else {
@@ -101,6 +104,14 @@ impl Buffer {
         Ok(())
     }
 
+    // forget the assigned block without writing it back, for a block that was freed.
+    // Its file may hand the block out again, and the page must not overwrite it then
+    pub fn unassign(&mut self) {
+        self.blk = None;
+        self.txnum = -1;
+        self.lsn = -1;
+    }
+
     pub fn pin(&mut self) {
         self.pins += 1;
     }
@@ -17,6 +17,7 @@ const MAX_TIME: i64 = 10000;
 enum BufferMgrError {
     LockFailed(String),
     BufferAbort,
+    BlockPinned(BlockId),
 }
 
 impl std::error::Error for BufferMgrError {}
@@ -29,6 +30,9 @@ impl fmt::Display for BufferMgrError {
             BufferMgrError::BufferAbort => {
                 write!(f, "buffer abort")
             }
+            BufferMgrError::BlockPinned(blk) => {
+                write!(f, "block still pinned: {}", blk)
+            }
         }
     }
 }
@@ -40,7 +44,9 @@ pub struct BufferMgr {
 }
 
 impl BufferMgr {
-    pub fn new(fm: Arc<RefCell<FileMgr>>, lm: Arc<RefCell<LogMgr>>, numbuffs: usize) -> BufferMgr {
+    // buffers are not shared between threads yet
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(fm: Arc<FileMgr>, lm: Arc<LogMgr>, numbuffs: usize) -> BufferMgr {
         let bufferpool: Vec<Arc<RefCell<Buffer>>> = (0..numbuffs)
             .map(|_| Arc::new(RefCell::new(Buffer::new(Arc::clone(&fm), Arc::clone(&lm)))))
             .collect();
@@ -67,6 +73,8 @@ impl BufferMgr {
                     self.bufferpool[i].borrow_mut().flush()?;
                 }
             }
+
+            return Ok(());
         }
 
         Err(From::from(BufferMgrError::LockFailed(
@@ -90,6 +98,26 @@ impl BufferMgr {
         ))))
     }
 
+    // drop the buffer of a block that is about to be freed, unflushed changes included,
+    // so that the pool never hands out or writes back what the block held before
+    pub fn discard(&mut self, blk: &BlockId) -> Result<()> {
+        if self.l.lock().is_ok() {
+            if let Some(i) = self.find_existing_buffer(blk) {
+                let mut buff = self.bufferpool[i].borrow_mut();
+                if buff.is_pinned() {
+                    return Err(From::from(BufferMgrError::BlockPinned(blk.clone())));
+                }
+                buff.unassign();
+            }
+
+            return Ok(());
+        }
+
+        Err(From::from(BufferMgrError::LockFailed(String::from(
+            "discard",
+        ))))
+    }
+
     pub fn pin(&mut self, blk: &BlockId) -> Result<Arc<RefCell<Buffer>>> {
         if self.l.lock().is_ok() {
             let timestamp = SystemTime::now();
@@ -152,13 +180,7 @@ impl BufferMgr {
     }
 
     pub fn choose_unpinned_buffer(&mut self) -> Option<usize> {
-        for i in 0..self.bufferpool.len() {
-            if !self.bufferpool[i].borrow().is_pinned() {
-                return Some(i);
-            }
-        }
-
-        None
+        (0..self.bufferpool.len()).find(|&i| !self.bufferpool[i].borrow().is_pinned())
     }
 }
 
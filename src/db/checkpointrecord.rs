@@ -0,0 +1,45 @@
+use super::logmanager::LogMgr;
+use super::logrecord::CHECKPOINT;
+use super::page::Page;
+
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+pub struct CheckpointRecord {}
+
+impl fmt::Display for CheckpointRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<CHECKPOINT>")
+    }
+}
+
+/*
+ * | Checkpoint |
+ *       int
+ **/
+impl CheckpointRecord {
+    pub fn new(_p: &Page) -> Result<CheckpointRecord> {
+        Ok(CheckpointRecord {})
+    }
+
+    pub fn op(&self) -> i32 {
+        CHECKPOINT
+    }
+
+    // a checkpoint belongs to no transaction
+    pub fn tx_number(&self) -> i32 {
+        -1
+    }
+
+    pub fn write_to_log(lm: Arc<LogMgr>) -> Result<u64> {
+        let reclen = mem::size_of::<i32>();
+
+        let mut p = Page::new_from_size(reclen);
+        p.set_int(0, CHECKPOINT)?;
+
+        lm.append(p.contents())
+    }
+}
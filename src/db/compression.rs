@@ -0,0 +1,106 @@
+// a small LZ77 codec for blocks, so compressed databases need no external library.
+// It favors speed over ratio: repeated strings and runs of zeroes (the unused tail of a page) shrink well.
+
+/*
+ * the compressed form is a sequence of tokens
+ *
+ * literals: | 0nnnnnnn | n+1 bytes |
+ * match:    | 1nnnnnnn | distance |    repeats n+MIN_MATCH bytes starting `distance` bytes back
+ *                           u16
+ */
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 0x7f + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+const MAX_DISTANCE: usize = u16::MAX as usize;
+
+const HASH_BITS: u32 = 10;
+
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len());
+    // the last position where each hashed 4 byte prefix was seen
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+
+    let mut literals = 0;
+    let mut i = 0;
+    while i + MIN_MATCH <= src.len() {
+        let h = hash(&src[i..i + MIN_MATCH]);
+        let candidate = table[h];
+        table[h] = i;
+
+        if candidate == usize::MAX
+            || i - candidate > MAX_DISTANCE
+            || src[candidate..candidate + MIN_MATCH] != src[i..i + MIN_MATCH]
+        {
+            i += 1;
+            continue;
+        }
+
+        // the match may overlap the bytes it produces, which is how runs are encoded
+        let mut len = MIN_MATCH;
+        while len < MAX_MATCH && i + len < src.len() && src[candidate + len] == src[i + len] {
+            len += 1;
+        }
+
+        push_literals(&mut out, &src[literals..i]);
+        out.push(0x80 | (len - MIN_MATCH) as u8);
+        out.extend_from_slice(&((i - candidate) as u16).to_be_bytes());
+
+        i += len;
+        literals = i;
+    }
+    push_literals(&mut out, &src[literals..]);
+
+    out
+}
+
+// None if src is not the compressed form of exactly len bytes
+pub fn decompress(mut src: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+
+    while let Some((&token, rest)) = src.split_first() {
+        src = rest;
+
+        if token & 0x80 == 0 {
+            let n = token as usize + 1;
+            if src.len() < n || out.len() + n > len {
+                return None;
+            }
+            out.extend_from_slice(&src[..n]);
+            src = &src[n..];
+        } else {
+            let n = (token & 0x7f) as usize + MIN_MATCH;
+            if src.len() < 2 {
+                return None;
+            }
+            let distance = u16::from_be_bytes([src[0], src[1]]) as usize;
+            src = &src[2..];
+            if distance == 0 || distance > out.len() || out.len() + n > len {
+                return None;
+            }
+
+            // byte by byte, the source may overlap what is being copied
+            for _ in 0..n {
+                out.push(out[out.len() - distance]);
+            }
+        }
+    }
+
+    if out.len() != len {
+        return None;
+    }
+
+    Some(out)
+}
+
+fn push_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn hash(prefix: &[u8]) -> usize {
+    let v = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+
+    (v.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
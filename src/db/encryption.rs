@@ -0,0 +1,111 @@
+use super::blockid::BlockId;
+use super::pagetransform::PageTransform;
+
+use anyhow::Result;
+use chacha20poly1305::aead::AeadInPlace;
+use chacha20poly1305::{KeyInit, Tag, XChaCha20Poly1305, XNonce};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+pub const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+const TAG_SIZE: usize = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum EncryptionError {
+    NoRandomness,
+    AuthenticationFailed(BlockId),
+}
+
+impl std::error::Error for EncryptionError {}
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncryptionError::NoRandomness => write!(f, "no randomness for a nonce"),
+            EncryptionError::AuthenticationFailed(blk) => {
+                write!(f, "block failed authentication: {}", blk)
+            }
+        }
+    }
+}
+
+// encrypts blocks at rest with XChaCha20-Poly1305 under a 256-bit key, given to FileMgr as its transform.
+// Every encode draws a fresh random nonce, so rewriting a block never reuses a key stream.
+// The file name and block number are authenticated along with the block, so a block that is
+// copied or moved to another place on disk fails to decode.
+pub struct Encryption {
+    cipher: XChaCha20Poly1305,
+    key_check: u64,
+}
+
+impl Encryption {
+    pub fn new(key: [u8; KEY_SIZE]) -> Encryption {
+        let mut digest = Sha256::new();
+        digest.update(b"simpledb key check");
+        digest.update(key);
+        let mut check = [0; 8];
+        check.copy_from_slice(&digest.finalize()[..8]);
+
+        Encryption {
+            cipher: XChaCha20Poly1305::new(&key.into()),
+            key_check: u64::from_be_bytes(check),
+        }
+    }
+
+    fn associated_data(blk: &BlockId) -> Vec<u8> {
+        let mut ad = blk.number().to_be_bytes().to_vec();
+        ad.extend_from_slice(blk.filename().as_bytes());
+
+        ad
+    }
+}
+
+/*
+ * an encoded block
+ *
+ * | ciphertext | nonce | tag |
+ *                 24     16
+ */
+impl PageTransform for Encryption {
+    fn overhead(&self) -> usize {
+        NONCE_SIZE + TAG_SIZE
+    }
+
+    fn check_value(&self) -> u64 {
+        self.key_check
+    }
+
+    fn encode(&self, blk: &BlockId, buf: &mut [u8]) -> Result<()> {
+        let (data, trailer) = buf.split_at_mut(buf.len() - self.overhead());
+        let (nonce, tag) = trailer.split_at_mut(NONCE_SIZE);
+        getrandom::getrandom(nonce).map_err(|_| EncryptionError::NoRandomness)?;
+
+        let sealed = self
+            .cipher
+            .encrypt_in_place_detached(
+                XNonce::from_slice(nonce),
+                &Encryption::associated_data(blk),
+                data,
+            )
+            .map_err(|_| EncryptionError::AuthenticationFailed(blk.clone()))?;
+        tag.copy_from_slice(&sealed);
+
+        Ok(())
+    }
+
+    fn decode(&self, blk: &BlockId, buf: &mut [u8]) -> Result<()> {
+        let (data, trailer) = buf.split_at_mut(buf.len() - self.overhead());
+        let (nonce, tag) = trailer.split_at(NONCE_SIZE);
+
+        self.cipher
+            .decrypt_in_place_detached(
+                XNonce::from_slice(nonce),
+                &Encryption::associated_data(blk),
+                data,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| EncryptionError::AuthenticationFailed(blk.clone()))?;
+
+        Ok(())
+    }
+}
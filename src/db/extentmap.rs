@@ -0,0 +1,339 @@
+use super::filemanager::FileMgrError;
+use super::storage::Storage;
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// the sidecar file of each compressed file, holding the extent of every block
+pub const EXTENTS_SUFFIX: &str = ".extents";
+
+// extents are allocated in units of this many bytes
+const SECTOR_SIZE: u64 = 64;
+
+/*
+ * the sidecar has one entry per block, the entry of block n lies at n * ENTRY_SIZE
+ *
+ * | start | sectors |
+ *    u64      u32
+ *
+ * the extent covers `sectors` sectors of the data file from sector `start`.
+ * An entry of zeroes is a block that has never been written.
+ */
+const ENTRY_SIZE: usize = 12;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Extent {
+    start: u64,
+    sectors: u64,
+}
+
+impl Extent {
+    fn encode(&self) -> [u8; ENTRY_SIZE] {
+        let mut entry = [0; ENTRY_SIZE];
+        entry[..8].copy_from_slice(&self.start.to_be_bytes());
+        entry[8..].copy_from_slice(&(self.sectors as u32).to_be_bytes());
+
+        entry
+    }
+
+    fn decode(entry: &[u8]) -> Result<Extent> {
+        Ok(Extent {
+            start: u64::from_be_bytes(entry[..8].try_into()?),
+            sectors: u32::from_be_bytes(entry[8..].try_into()?) as u64,
+        })
+    }
+}
+
+// the extents of one file and the sectors between them
+struct FileExtents {
+    extents: Vec<Extent>,
+    // unused runs of sectors, start -> number of sectors
+    free: BTreeMap<u64, u64>,
+    // the number of sectors in the data file
+    end: u64,
+}
+
+impl FileExtents {
+    // the free runs are whatever no extent covers, so they need not be persisted
+    fn new(extents: Vec<Extent>) -> FileExtents {
+        let mut used: Vec<Extent> = extents.iter().filter(|e| e.sectors > 0).copied().collect();
+        used.sort_by_key(|e| e.start);
+
+        let mut free = BTreeMap::new();
+        let mut end = 0;
+        for e in used {
+            if e.start > end {
+                free.insert(end, e.start - end);
+            }
+            end = end.max(e.start + e.sectors);
+        }
+
+        FileExtents { extents, free, end }
+    }
+
+    fn get(&self, blknum: u64) -> Extent {
+        self.extents
+            .get(blknum as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // first fit among the free runs, else grow the data file
+    fn allocate(&mut self, sectors: u64) -> Extent {
+        let run = self
+            .free
+            .iter()
+            .find(|(_, n)| **n >= sectors)
+            .map(|(start, n)| (*start, *n));
+
+        let start = match run {
+            Some((start, n)) => {
+                self.free.remove(&start);
+                if n > sectors {
+                    self.free.insert(start + sectors, n - sectors);
+                }
+                start
+            }
+            None => {
+                self.end += sectors;
+                self.end - sectors
+            }
+        };
+
+        Extent { start, sectors }
+    }
+
+    // give the sectors back, merged with the free runs around them
+    fn release(&mut self, e: Extent) {
+        let (mut start, mut sectors) = (e.start, e.sectors);
+        if let Some(n) = self.free.remove(&(start + sectors)) {
+            sectors += n;
+        }
+        let prev = self.free.range(..start).next_back().map(|(s, n)| (*s, *n));
+        if let Some((s, n)) = prev.filter(|(s, n)| s + n == start) {
+            self.free.remove(&s);
+            start = s;
+            sectors += n;
+        }
+
+        self.free.insert(start, sectors);
+    }
+
+    fn set(&mut self, blknum: u64, e: Extent) {
+        let blknum = blknum as usize;
+        if self.extents.len() <= blknum {
+            self.extents.resize(blknum + 1, Extent::default());
+        }
+        self.extents[blknum] = e;
+    }
+}
+
+// maps the blocks of compressed files, which vary in length, to extents of their data files.
+// A block is rewritten in place while it fits its extent, and moved to a new extent when it has
+// grown: the data is written first and the entry after it, so a crash in between leaves the old
+// block readable. The sectors of the old extent are reused right away, so an entry that has not
+// reached the disk yet may point at data of another block, which the block checksum (it covers
+// the filename and block number) then reports.
+pub struct ExtentMap {
+    // one lock per file, held while its blocks are read or written, as a block must not be read
+    // while its extent is being moved. The map itself is only locked to look the file up
+    files: Mutex<HashMap<String, Arc<FileLock>>>,
+}
+
+// None until the sidecar has been loaded
+type FileLock = Mutex<Option<FileExtents>>;
+
+impl ExtentMap {
+    pub fn new() -> ExtentMap {
+        ExtentMap {
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // the stored bytes of the block, padded to whole sectors.
+    // None if it has never been written
+    pub fn read(
+        &self,
+        storage: &dyn Storage,
+        filename: &str,
+        blknum: u64,
+    ) -> Result<Option<Vec<u8>>> {
+        let file = self.file_lock(filename)?;
+        let mut fe = ExtentMap::lock(&file, filename)?;
+        let e = ExtentMap::load(&mut fe, storage, filename)?.get(blknum);
+        if e.sectors == 0 {
+            return Ok(None);
+        }
+
+        let mut buf = vec![0; (e.sectors * SECTOR_SIZE) as usize];
+        storage.read_at(filename, e.start * SECTOR_SIZE, &mut buf)?;
+
+        Ok(Some(buf))
+    }
+
+    pub fn write(
+        &self,
+        storage: &dyn Storage,
+        filename: &str,
+        blknum: u64,
+        buf: &[u8],
+    ) -> Result<()> {
+        let file = self.file_lock(filename)?;
+        let mut fe = ExtentMap::lock(&file, filename)?;
+        let fe = ExtentMap::load(&mut fe, storage, filename)?;
+
+        ExtentMap::write_extent(fe, storage, filename, blknum, buf)
+    }
+
+    // add a block at the end of the file and return its block number.
+    // It has no extent yet, so it reads as zeroes until it is written
+    pub fn append(&self, storage: &dyn Storage, filename: &str) -> Result<u64> {
+        let file = self.file_lock(filename)?;
+        let mut fe = ExtentMap::lock(&file, filename)?;
+        let fe = ExtentMap::load(&mut fe, storage, filename)?;
+        let blknum = fe.extents.len() as u64;
+        let e = Extent::default();
+        storage.write_block(&extents_filename(filename), blknum, &e.encode())?;
+        fe.set(blknum, e);
+
+        Ok(blknum)
+    }
+
+    pub fn length(&self, storage: &dyn Storage, filename: &str) -> Result<u64> {
+        let file = self.file_lock(filename)?;
+        let mut fe = ExtentMap::lock(&file, filename)?;
+
+        Ok(ExtentMap::load(&mut fe, storage, filename)?.extents.len() as u64)
+    }
+
+    pub fn truncate(&self, storage: &dyn Storage, filename: &str, nblocks: u64) -> Result<()> {
+        let file = self.file_lock(filename)?;
+        let mut fe = ExtentMap::lock(&file, filename)?;
+        let fe = ExtentMap::load(&mut fe, storage, filename)?;
+        if fe.extents.len() as u64 <= nblocks {
+            return Ok(());
+        }
+
+        let mut extents = std::mem::take(&mut fe.extents);
+        extents.truncate(nblocks as usize);
+        *fe = FileExtents::new(extents);
+
+        storage.truncate(&extents_filename(filename), nblocks, ENTRY_SIZE as u64)?;
+        storage.truncate(filename, fe.end, SECTOR_SIZE)
+    }
+
+    pub fn delete(&self, storage: &dyn Storage, filename: &str) -> Result<()> {
+        let file = self.file_lock(filename)?;
+        let mut fe = ExtentMap::lock(&file, filename)?;
+        // reloaded (as empty) if the file is used again
+        *fe = None;
+
+        let deleted = storage
+            .delete(filename)
+            .and_then(|_| storage.delete(&extents_filename(filename)));
+
+        // forget the file unless another thread is about to use it.
+        // Handles are only taken out under the map lock, so none can appear meanwhile
+        let mut files = self.lock_files(filename)?;
+        if Arc::strong_count(&file) == 2 {
+            files.remove(filename);
+        }
+
+        deleted
+    }
+
+    pub fn sync(&self, storage: &dyn Storage, filename: &str) -> Result<()> {
+        storage.sync(filename)?;
+        storage.sync(&extents_filename(filename))
+    }
+
+    fn write_extent(
+        fe: &mut FileExtents,
+        storage: &dyn Storage,
+        filename: &str,
+        blknum: u64,
+        buf: &[u8],
+    ) -> Result<()> {
+        let sectors = (buf.len() as u64).div_ceil(SECTOR_SIZE);
+        let old = fe.get(blknum);
+        if old.sectors >= sectors {
+            return storage.write_at(filename, old.start * SECTOR_SIZE, buf);
+        }
+
+        let e = fe.allocate(sectors);
+        let written = storage
+            .write_at(filename, e.start * SECTOR_SIZE, buf)
+            .and_then(|_| storage.write_block(&extents_filename(filename), blknum, &e.encode()));
+        if let Err(err) = written {
+            fe.release(e);
+            return Err(err);
+        }
+
+        fe.set(blknum, e);
+        if old.sectors > 0 {
+            fe.release(old);
+        }
+
+        Ok(())
+    }
+
+    fn lock_files(&self, filename: &str) -> Result<MutexGuard<'_, HashMap<String, Arc<FileLock>>>> {
+        Ok(self
+            .files
+            .lock()
+            .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?)
+    }
+
+    // the lock of the file, the map lock is released before it is taken
+    fn file_lock(&self, filename: &str) -> Result<Arc<FileLock>> {
+        let mut files = self.lock_files(filename)?;
+
+        Ok(Arc::clone(
+            files
+                .entry(filename.into())
+                .or_insert_with(|| Arc::new(Mutex::new(None))),
+        ))
+    }
+
+    fn lock<'a>(file: &'a FileLock, filename: &str) -> Result<MutexGuard<'a, Option<FileExtents>>> {
+        Ok(file
+            .lock()
+            .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?)
+    }
+
+    // the extents of the file, read from its sidecar when first used
+    fn load<'a>(
+        fe: &'a mut Option<FileExtents>,
+        storage: &dyn Storage,
+        filename: &str,
+    ) -> Result<&'a mut FileExtents> {
+        if fe.is_none() {
+            let sidecar = extents_filename(filename);
+            let n = storage.length(&sidecar, ENTRY_SIZE as u64)? as usize;
+            // a torn last entry reads as zeroes past its end
+            let mut buf = vec![0; n * ENTRY_SIZE];
+            storage.read_at(&sidecar, 0, &mut buf)?;
+
+            let extents = buf
+                .chunks(ENTRY_SIZE)
+                .map(Extent::decode)
+                .collect::<Result<Vec<_>>>()?;
+            *fe = Some(FileExtents::new(extents));
+        }
+
+        fe.as_mut()
+            .ok_or_else(|| From::from(FileMgrError::FileAccessFailed(filename.into())))
+    }
+}
+
+impl Default for ExtentMap {
+    fn default() -> Self {
+        ExtentMap::new()
+    }
+}
+
+pub fn extents_filename(filename: &str) -> String {
+    format!("{}{}", filename, EXTENTS_SUFFIX)
+}
@@ -0,0 +1,345 @@
+use super::blockid::BlockId;
+use super::storage::{HandleCacheStats, Storage};
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    // the first n block writes (writes and appends) succeed, every later access fails as if the process died
+    CrashAfterWrites(u64),
+    // the nth block write (counted from 1) persists only its first `bytes` bytes, then the process dies
+    TornWrite { nth: u64, bytes: usize },
+    // writes that grow the file (appends included) fail as if the disk were full
+    NoSpace(String),
+    // reads of the block fail
+    ReadError(BlockId),
+    // reads of the file that cover the byte at offset fail, whatever path they take
+    // (e.g. the extents of a compressed file, which are not block sized)
+    ReadErrorAt { filename: String, offset: u64 },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FaultError {
+    Crashed,
+    NoSpace(String),
+    ReadFailed(BlockId),
+    ReadFailedAt { filename: String, offset: u64 },
+    LockFailed,
+}
+
+impl std::error::Error for FaultError {}
+impl fmt::Display for FaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultError::Crashed => write!(f, "injected crash"),
+            FaultError::NoSpace(filename) => {
+                write!(f, "no space left on device: {}", filename)
+            }
+            FaultError::ReadFailed(blk) => write!(f, "injected read error: {}", blk),
+            FaultError::ReadFailedAt { filename, offset } => {
+                write!(f, "injected read error: {} at {}", filename, offset)
+            }
+            FaultError::LockFailed => write!(f, "lock failed"),
+        }
+    }
+}
+
+struct FaultState {
+    faults: Vec<Fault>,
+    writes: u64,
+    crashed: bool,
+    // the writes of each file since it was last synced, oldest first.
+    // Only kept when writes are volatile, a crash drops them
+    unsynced: HashMap<String, Vec<(u64, Vec<u8>)>>,
+}
+
+// wraps another backend and fails its operations as scripted with inject().
+// Faults are deterministic: they are triggered by counting block writes, not by time or chance.
+// After a crash every operation fails, the test drops the FileMgr and reopens the inner storage
+// (e.g. the database directory) to see what survived.
+pub struct FaultStorage {
+    inner: Box<dyn Storage>,
+    state: Mutex<FaultState>,
+    // writes only reach the inner storage when their file is synced, like a page cache
+    volatile: bool,
+}
+
+impl FaultStorage {
+    // every write reaches the inner storage at once, so a crash keeps all of them
+    pub fn new(inner: Box<dyn Storage>) -> FaultStorage {
+        FaultStorage::new_with_volatility(inner, false)
+    }
+
+    // writes are held back until their file is synced and a crash loses them,
+    // which shows what the durability policy really keeps.
+    // Truncating a file reaches the disk at once, together with the writes before it
+    pub fn new_with_volatile_writes(inner: Box<dyn Storage>) -> FaultStorage {
+        FaultStorage::new_with_volatility(inner, true)
+    }
+
+    fn new_with_volatility(inner: Box<dyn Storage>, volatile: bool) -> FaultStorage {
+        FaultStorage {
+            inner,
+            state: Mutex::new(FaultState {
+                faults: vec![],
+                writes: 0,
+                crashed: false,
+                unsynced: HashMap::new(),
+            }),
+            volatile,
+        }
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        if let Ok(mut state) = self.state.lock() {
+            state.faults.push(fault);
+        }
+    }
+
+    // remove all faults and restart counting writes (a crash is not undone)
+    pub fn clear(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.faults.clear();
+            state.writes = 0;
+        }
+    }
+
+    // the number of block writes seen since the last clear()
+    pub fn writes(&self) -> u64 {
+        self.state.lock().map_or(0, |state| state.writes)
+    }
+
+    pub fn crashed(&self) -> bool {
+        self.state.lock().is_ok_and(|state| state.crashed)
+    }
+
+    fn check_alive(&self) -> Result<MutexGuard<'_, FaultState>> {
+        let state = self.state.lock().map_err(|_| FaultError::LockFailed)?;
+        if state.crashed {
+            return Err(From::from(FaultError::Crashed));
+        }
+
+        Ok(state)
+    }
+
+    // the length of the file in bytes, with the writes that have not reached the inner storage
+    fn file_length(&self, state: &FaultState, filename: &str) -> Result<u64> {
+        let synced = self.inner.length(filename, 1)?;
+        let unsynced = state.unsynced.get(filename).into_iter().flatten();
+
+        Ok(unsynced.fold(synced, |len, (offset, buf)| {
+            len.max(offset + buf.len() as u64)
+        }))
+    }
+
+    // hand the held back writes of the file to the inner storage
+    fn write_unsynced(&self, state: &mut FaultState, filename: &str) -> Result<()> {
+        for (offset, buf) in state.unsynced.remove(filename).unwrap_or_default() {
+            self.inner.write_at(filename, offset, &buf)?;
+        }
+
+        Ok(())
+    }
+
+    // count a block write and return how many of its bytes should reach the inner storage
+    fn before_write(&self, buf: &[u8]) -> Result<usize> {
+        let mut state = self.state.lock().map_err(|_| FaultError::LockFailed)?;
+        if state.crashed {
+            return Err(From::from(FaultError::Crashed));
+        }
+
+        state.writes += 1;
+        let nth = state.writes;
+
+        let mut persisted = buf.len();
+        for fault in state.faults.iter() {
+            match fault {
+                Fault::CrashAfterWrites(n) if nth > *n => persisted = 0,
+                Fault::TornWrite { nth: n, bytes } if nth == *n => {
+                    persisted = persisted.min(*bytes)
+                }
+                _ => {}
+            }
+        }
+
+        if persisted < buf.len() {
+            state.crashed = true;
+            state.unsynced.clear();
+        }
+
+        Ok(persisted)
+    }
+
+    // the bytes at offset as they look after only the first `persisted` bytes of buf reached them
+    fn torn_block(
+        &self,
+        filename: &str,
+        offset: u64,
+        buf: &[u8],
+        persisted: usize,
+    ) -> Result<Vec<u8>> {
+        let mut block = vec![0; buf.len()];
+        self.inner.read_at(filename, offset, &mut block)?;
+        block[..persisted].copy_from_slice(&buf[..persisted]);
+
+        Ok(block)
+    }
+}
+
+impl Storage for FaultStorage {
+    fn read_block(&self, filename: &str, blknum: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_at(filename, blknum * buf.len() as u64, buf)
+    }
+
+    fn write_block(&self, filename: &str, blknum: u64, buf: &[u8]) -> Result<()> {
+        self.write_at(filename, blknum * buf.len() as u64, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let state = self.check_alive()?;
+
+        let end = offset + buf.len() as u64;
+        for fault in state.faults.iter() {
+            match fault {
+                // a read of the whole block
+                Fault::ReadError(blk)
+                    if blk.filename() == filename && blk.number() * buf.len() as u64 == offset =>
+                {
+                    return Err(From::from(FaultError::ReadFailed(blk.clone())));
+                }
+                Fault::ReadErrorAt {
+                    filename: f,
+                    offset: o,
+                } if f == filename && (offset..end).contains(o) => {
+                    return Err(From::from(FaultError::ReadFailedAt {
+                        filename: f.clone(),
+                        offset: *o,
+                    }));
+                }
+                _ => {}
+            }
+        }
+
+        buf.iter_mut().for_each(|b| *b = 0);
+        let mut read_len = self.inner.read_at(filename, offset, buf)? as u64;
+        // the held back writes cover what the inner storage has
+        for (o, data) in state.unsynced.get(filename).into_iter().flatten() {
+            let (start, stop) = ((*o).max(offset), (o + data.len() as u64).min(end));
+            if start < stop {
+                buf[(start - offset) as usize..(stop - offset) as usize]
+                    .copy_from_slice(&data[(start - o) as usize..(stop - o) as usize]);
+                read_len = read_len.max(stop - offset);
+            }
+        }
+
+        Ok(read_len as usize)
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<()> {
+        {
+            let state = self.check_alive()?;
+            if state.faults.contains(&Fault::NoSpace(filename.into()))
+                && offset + buf.len() as u64 > self.file_length(&state, filename)?
+            {
+                return Err(From::from(FaultError::NoSpace(filename.into())));
+            }
+        }
+        let persisted = self.before_write(buf)?;
+        if persisted == buf.len() && self.volatile {
+            let mut state = self.check_alive()?;
+            let unsynced = state.unsynced.entry(filename.into()).or_default();
+            unsynced.push((offset, buf.to_vec()));
+
+            return Ok(());
+        }
+        if persisted == buf.len() {
+            return self.inner.write_at(filename, offset, buf);
+        }
+
+        if persisted > 0 {
+            let block = self.torn_block(filename, offset, buf, persisted)?;
+            self.inner.write_at(filename, offset, &block)?;
+        }
+
+        Err(From::from(FaultError::Crashed))
+    }
+
+    fn append_block(&self, filename: &str, buf: &[u8]) -> Result<u64> {
+        if self
+            .check_alive()?
+            .faults
+            .contains(&Fault::NoSpace(filename.into()))
+        {
+            return Err(From::from(FaultError::NoSpace(filename.into())));
+        }
+
+        let persisted = self.before_write(buf)?;
+        if persisted == buf.len() && self.volatile {
+            let mut state = self.check_alive()?;
+            let blknum = self
+                .file_length(&state, filename)?
+                .div_ceil(buf.len() as u64);
+            let unsynced = state.unsynced.entry(filename.into()).or_default();
+            unsynced.push((blknum * buf.len() as u64, buf.to_vec()));
+
+            return Ok(blknum);
+        }
+        if persisted == buf.len() {
+            return self.inner.append_block(filename, buf);
+        }
+
+        if persisted > 0 {
+            let mut block = vec![0; buf.len()];
+            block[..persisted].copy_from_slice(&buf[..persisted]);
+            self.inner.append_block(filename, &block)?;
+        }
+
+        Err(From::from(FaultError::Crashed))
+    }
+
+    fn length(&self, filename: &str, blocksize: u64) -> Result<u64> {
+        let state = self.check_alive()?;
+        if !state.unsynced.contains_key(filename) {
+            return self.inner.length(filename, blocksize);
+        }
+
+        Ok(self.file_length(&state, filename)?.div_ceil(blocksize))
+    }
+
+    fn truncate(&self, filename: &str, nblocks: u64, blocksize: u64) -> Result<()> {
+        let mut state = self.check_alive()?;
+        self.write_unsynced(&mut state, filename)?;
+        self.inner.truncate(filename, nblocks, blocksize)
+    }
+
+    fn delete(&self, filename: &str) -> Result<()> {
+        let mut state = self.check_alive()?;
+        state.unsynced.remove(filename);
+        self.inner.delete(filename)
+    }
+
+    fn sync(&self, filename: &str) -> Result<()> {
+        let mut state = self.check_alive()?;
+        self.write_unsynced(&mut state, filename)?;
+        self.inner.sync(filename)
+    }
+
+    fn filenames(&self) -> Result<Vec<String>> {
+        let state = self.check_alive()?;
+        let mut filenames = self.inner.filenames()?;
+        for filename in state.unsynced.keys() {
+            if !filenames.contains(filename) {
+                filenames.push(filename.clone());
+            }
+        }
+
+        Ok(filenames)
+    }
+
+    fn handle_cache_stats(&self) -> Option<HandleCacheStats> {
+        self.inner.handle_cache_stats()
+    }
+}
@@ -1,20 +1,63 @@
+use super::alignedbuf::AlignedBuf;
 use super::blockid::BlockId;
+use super::checksum::crc32;
+use super::compression;
+use super::extentmap::ExtentMap;
+use super::filestorage::{FileStorage, IoMode, LOCK_FILE};
+use super::freelist::{FreeList, FREELIST_FILE};
+use super::iostats::{IoOp, IoStats};
 use super::page::Page;
+use super::pagetransform::PageTransform;
+use super::storage::{HandleCacheStats, Storage};
+use super::superblock::{Superblock, FLAG_COMPRESSED, FLAG_TRANSFORMED, SUPERBLOCK_FILE};
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::convert::TryInto;
 use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::prelude::*;
-use std::io::SeekFrom;
+use std::mem;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
-enum FileMgrError {
+// files whose name starts with this are temporary, FileMgr::new removes any left over from a crash
+pub const TEMP_PREFIX: &str = "temp";
+
+// every block on disk is followed by the checksum of its contents (see encode_slot)
+const CHECKSUM_SIZE: u64 = mem::size_of::<u32>() as u64;
+
+/*
+ * with compression, a block is stored as an extent (see ExtentMap)
+ *
+ * | len | crc32 | method | data | transform overhead |
+ *   u32    u32     u8
+ *
+ * len covers method, data and the overhead of the page transform, which encodes them as one. crc32 covers them
+ * together with the filename and block number, so an extent that an outdated sidecar entry
+ * points at is only accepted if it holds that very block. The data is the page itself (RAW)
+ * when compressing didn't make it smaller, an LZ token stream otherwise
+ */
+const EXTENT_HEADER_SIZE: usize = 8;
+const RAW: u8 = 0;
+const LZ: u8 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum FileMgrError {
     ParseFailed,
     FileAccessFailed(String),
+    Corrupted(BlockId),
+    DatabaseInUse(String),
+    NotADatabase,
+    UnsupportedVersion(u32),
+    BlockSizeMismatch { stored: u64, requested: u64 },
+    BlockOutOfRange(BlockId),
+    InvalidBlockSize { blocksize: u64, alignment: usize },
+    ReservedFile(String),
+    FeatureMismatch { feature: String, stored: bool },
+    TransformMismatch,
+    ReadOnly(String),
+    IncompatibleOptions(String),
 }
 
 impl std::error::Error for FileMgrError {}
@@ -25,145 +68,655 @@ impl fmt::Display for FileMgrError {
             FileMgrError::FileAccessFailed(filename) => {
                 write!(f, "file access failed: {}", filename)
             }
+            FileMgrError::Corrupted(blk) => write!(f, "block corrupted: {}", blk),
+            FileMgrError::DatabaseInUse(dir) => write!(f, "database in use: {}", dir),
+            FileMgrError::NotADatabase => write!(f, "not a database: bad superblock"),
+            FileMgrError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version: {}", version)
+            }
+            FileMgrError::BlockSizeMismatch { stored, requested } => write!(
+                f,
+                "block size mismatch: database has {}, opened with {}",
+                stored, requested
+            ),
+            FileMgrError::BlockOutOfRange(blk) => write!(f, "block out of range: {}", blk),
+            FileMgrError::InvalidBlockSize {
+                blocksize,
+                alignment,
+            } => write!(
+                f,
+                "invalid block size for direct I/O: {} plus a {} byte checksum and the page transform overhead is not a multiple of {}",
+                blocksize, CHECKSUM_SIZE, alignment
+            ),
+            FileMgrError::ReservedFile(filename) => {
+                write!(f, "file is managed by FileMgr: {}", filename)
+            }
+            FileMgrError::FeatureMismatch { feature, stored } => write!(
+                f,
+                "database was created {} {}",
+                if *stored { "with" } else { "without" },
+                feature
+            ),
+            FileMgrError::ReadOnly(filename) => {
+                write!(f, "database is open read-only: {}", filename)
+            }
+            FileMgrError::TransformMismatch => {
+                write!(f, "page transform does not match the database (wrong key?)")
+            }
+            FileMgrError::IncompatibleOptions(reason) => {
+                write!(f, "incompatible options: {}", reason)
+            }
         }
     }
 }
 
+// when written blocks are forced to disk with fsync
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    // never sync, leave it to the OS (fast, for tests and scratch databases)
+    None,
+    // sync the log file whenever LogMgr flushes it, so a flushed log record survives a power failure.
+    // A flush (one group commit) costs a single block write plus sync: the last log block is written
+    // alternately in place and to its copy in the .shadow file, so a torn write loses no flushed record.
+    // Moving on to a new block, or starting a log iterator, may take one more write
+    LogFlush,
+    // sync every block write and append
+    EveryWrite,
+}
+
+pub struct FileMgrOptions {
+    pub durability: Durability,
+    // the most file handles kept open at once, the least recently used is closed beyond that
+    pub max_open_files: usize,
+    pub io_mode: IoMode,
+    // what IoMode::Direct aligns memory, offsets and lengths to (a power of two).
+    // 4096 suits every common device, 512 is enough for most disks with 512 byte sectors
+    pub direct_io_alignment: usize,
+    // compress each block, which pays off for tables of repetitive strings.
+    // Recorded in the superblock: a database must always be opened with the setting it was created with.
+    // Not with IoMode::Direct, extents are packed without regard to alignment
+    pub compression: bool,
+    // applied to every block on its way to and from the storage, log blocks included.
+    // Set it to Encryption with the database key to keep the files unreadable without the key.
+    // The superblock records a check value, so opening with another transform (or key) is refused
+    pub transform: Option<Box<dyn PageTransform>>,
+    // inspect an existing database without any chance of changing it: files are opened read-only
+    // under a shared lock, nothing is created or deleted, and writes fail with FileMgrError::ReadOnly
+    pub read_only: bool,
+}
+
+impl Default for FileMgrOptions {
+    fn default() -> Self {
+        FileMgrOptions {
+            durability: Durability::LogFlush,
+            max_open_files: 128,
+            io_mode: IoMode::Standard,
+            direct_io_alignment: 4096,
+            compression: false,
+            transform: None,
+            read_only: false,
+        }
+    }
+}
+
+// FileMgr is shared between threads as Arc<FileMgr>.
+// The blocks themselves are kept by a Storage backend, files on disk by default.
 pub struct FileMgr {
-    db_directory: String,
+    storage: Box<dyn Storage>,
     blocksize: u64,
     is_new: bool,
-    open_files: HashMap<String, File>,
-    l: Arc<Mutex<()>>,
+    durability: Durability,
+    // the alignment of the buffers handed to the storage
+    alignment: usize,
+    // where the blocks of each file lie when they are compressed, None without compression
+    extents: Option<ExtentMap>,
+    transform: Option<Box<dyn PageTransform>>,
+    // freed blocks that append hands out again, persisted in FREELIST_FILE
+    freelist: Mutex<FreeList>,
+    // temporary files handed out by new_temp_filename and not deleted yet
+    temp_files: Mutex<HashSet<String>>,
+    next_temp: AtomicU64,
+    // reads, writes and appends of each file since the last reset_io_stats
+    io_stats: Mutex<IoStats>,
+    read_only: bool,
 }
 
 impl FileMgr {
     pub fn new(db_directory: &str, blocksize: u64) -> Result<FileMgr> {
-        let path = Path::new(db_directory);
-        let is_new = !path.exists();
+        FileMgr::new_with_options(db_directory, blocksize, FileMgrOptions::default())
+    }
 
-        if is_new {
-            fs::create_dir_all(path)?;
+    pub fn new_with_options(
+        db_directory: &str,
+        blocksize: u64,
+        options: FileMgrOptions,
+    ) -> Result<FileMgr> {
+        // refuse a directory of other files before the storage leaves its lock file in it
+        if holds_other_files(Path::new(db_directory))? {
+            return Err(From::from(FileMgrError::NotADatabase));
         }
+        let storage = FileStorage::new_with_options(db_directory, &options)?;
 
-        // remove any leftover temporary tables
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let epath = entry.path();
-            let filename = match entry.file_name().into_string() {
-                Ok(s) => s,
-                Err(_) => return Err(From::from(FileMgrError::ParseFailed)),
-            };
+        FileMgr::new_from_storage(Box::new(storage), blocksize, options)
+    }
 
-            if filename.starts_with("temp") {
-                fs::remove_file(epath)?;
+    pub fn new_from_storage(
+        storage: Box<dyn Storage>,
+        blocksize: u64,
+        options: FileMgrOptions,
+    ) -> Result<FileMgr> {
+        if options.compression && options.io_mode == IoMode::Direct {
+            return Err(From::from(FileMgrError::IncompatibleOptions(
+                "compression with direct I/O".into(),
+            )));
+        }
+
+        // with direct I/O, every block (with its checksum) must cover whole aligned units
+        let overhead = options
+            .transform
+            .as_ref()
+            .map_or(0, |t| t.overhead() as u64);
+        let alignment = if options.io_mode == IoMode::Direct {
+            let alignment = options.direct_io_alignment;
+            if !alignment.is_power_of_two()
+                || !(blocksize + overhead + CHECKSUM_SIZE).is_multiple_of(alignment as u64)
+            {
+                return Err(From::from(FileMgrError::InvalidBlockSize {
+                    blocksize,
+                    alignment,
+                }));
             }
+            alignment
+        } else {
+            1
+        };
+
+        let mut flags = 0;
+        if options.compression {
+            flags |= FLAG_COMPRESSED;
+        }
+        let mut transform_check = 0;
+        if let Some(transform) = options.transform.as_ref() {
+            flags |= FLAG_TRANSFORMED;
+            transform_check = transform.check_value();
         }
 
-        Ok(FileMgr {
-            db_directory: String::from(db_directory),
+        // a new database gets a superblock, an existing one must match it
+        let mut filenames = storage.filenames()?;
+        let is_new = if filenames.iter().any(|f| f == SUPERBLOCK_FILE) {
+            Superblock::read(storage.as_ref())?.check(blocksize, flags, transform_check)?;
+            false
+        } else if filenames.is_empty() && !options.read_only {
+            Superblock::new(blocksize, flags, transform_check).write(storage.as_ref())?;
+            true
+        } else {
+            return Err(From::from(FileMgrError::NotADatabase));
+        };
+
+        // remove any leftover temporary tables, only once the files are known to be a database.
+        // (a read-only database may still have them, nothing can write them anymore)
+        if !options.read_only {
+            for filename in filenames.iter().filter(|f| f.starts_with(TEMP_PREFIX)) {
+                storage.delete(filename)?;
+            }
+            filenames.retain(|f| !f.starts_with(TEMP_PREFIX));
+        }
+
+        let mut fm = FileMgr {
+            storage,
             blocksize,
             is_new,
-            open_files: HashMap::new(),
-            l: Arc::new(Mutex::default()),
-        })
+            durability: options.durability,
+            alignment,
+            extents: if options.compression {
+                Some(ExtentMap::new())
+            } else {
+                None
+            },
+            transform: options.transform,
+            freelist: Mutex::new(FreeList::default()),
+            temp_files: Mutex::new(HashSet::new()),
+            next_temp: AtomicU64::new(0),
+            io_stats: Mutex::new(IoStats::default()),
+            read_only: options.read_only,
+        };
+
+        let mut freelist = FreeList::load(&fm)?;
+        if freelist.retain_files(&filenames) && !fm.read_only {
+            fm.save_freelist(&freelist)?;
+        }
+        fm.freelist = Mutex::new(freelist);
+
+        Ok(fm)
     }
 
     // write the content of buf into p
-    pub fn read(&mut self, blk: &BlockId, p: &mut Page) -> anyhow::Result<()> {
-        if self.l.lock().is_ok() {
-            let blocksize = self.blocksize;
-            let f = self.configure_file_table(blk.filename())?;
+    // the checksum stored with the block is verified, a mismatch is reported as FileMgrError::Corrupted
+    pub fn read(&self, blk: &BlockId, p: &mut Page) -> Result<()> {
+        let start = Instant::now();
+        let bytes = match self.extents.as_ref() {
+            Some(extents) => self.read_extent(extents, blk, p)?,
+            None => self.read_slot(blk, p)?,
+        };
+        self.record_io(blk.filename(), IoOp::Read, bytes, start.elapsed());
 
-            let offset = blk.number() * blocksize;
-            f.seek(SeekFrom::Start(offset))?;
+        Ok(())
+    }
 
-            // ERROR: bytes are not added because of ...
-            let read_len = f.read(p.contents())?;
+    // write all contents of p into the file refered in blk
+    pub fn write(&self, blk: &BlockId, p: &mut Page) -> Result<()> {
+        self.check_writable(blk.filename())?;
+        let start = Instant::now();
+        let bytes = self.write_contents(blk, p.contents())?;
 
-            if read_len < p.contents().len() {
-                for i in read_len..p.contents().len() {
-                    p.contents()[i] = 0;
-                }
+        if self.durability == Durability::EveryWrite {
+            self.sync(blk.filename())?;
+        }
+        self.record_io(blk.filename(), IoOp::Write, bytes, start.elapsed());
+
+        Ok(())
+    }
+
+    // hand out a freed block of the file, or write an empty array of bytes to the end of the file
+    // either way the new block is all zeroes
+    pub fn append(&self, filename: impl Into<String>) -> Result<BlockId> {
+        let start = Instant::now();
+        let filename = filename.into();
+        self.check_writable(&filename)?;
+        let b: Vec<u8> = vec![0; self.blocksize as usize];
+
+        let mut freelist = self.lock_freelist(&filename)?;
+        let (newblknum, bytes) = match freelist.pop(&filename) {
+            Some(blknum) => {
+                self.save_freelist(&freelist)?;
+                let bytes = self.write_contents(&BlockId::new(&filename, blknum), &b)?;
+                (blknum, bytes)
             }
+            // the sidecar entry of a compressed block tells that it has never been written.
+            // Otherwise the zeroes are stored with their checksum like any block, so that a
+            // slot zeroed on disk is reported as damage. Appends are serialized by the free list lock
+            None => match self.extents.as_ref() {
+                Some(extents) => (extents.append(self.storage.as_ref(), &filename)?, 0),
+                None => {
+                    let blknum = self.storage.length(&filename, self.slotsize())?;
+                    let bytes = self.write_contents(&BlockId::new(&filename, blknum), &b)?;
+                    (blknum, bytes)
+                }
+            },
+        };
+        drop(freelist);
 
-            return Ok(());
+        if self.durability == Durability::EveryWrite {
+            self.sync(&filename)?;
         }
+        self.record_io(&filename, IoOp::Append, bytes, start.elapsed());
 
-        Err(From::from(FileMgrError::FileAccessFailed(
-            blk.filename().into(),
-        )))
+        Ok(BlockId::new(&filename, newblknum))
     }
 
-    // write all contents of p into the file refered in blk
-    pub fn write(&mut self, blk: &BlockId, p: &mut Page) -> anyhow::Result<()> {
-        if self.l.lock().is_ok() {
-            let blocksize = self.blocksize;
-            let f = self.configure_file_table(blk.filename())?;
-            let offset = blk.number() * blocksize;
-            f.seek(SeekFrom::Start(offset))?;
-            f.write_all(p.contents())?;
+    // a snapshot of the I/O counters, per file and in total
+    pub fn io_stats(&self) -> IoStats {
+        self.io_stats
+            .lock()
+            .map_or(IoStats::default(), |stats| stats.clone())
+    }
 
-            return Ok(());
+    // start counting from zero again
+    pub fn reset_io_stats(&self) {
+        if let Ok(mut stats) = self.io_stats.lock() {
+            *stats = IoStats::default();
         }
-
-        Err(From::from(FileMgrError::FileAccessFailed(
-            blk.filename().into(),
-        )))
     }
 
-    // seek to the end of the file and write an empty array of bytes to it
-    pub fn append(&mut self, filename: impl Into<String>) -> anyhow::Result<BlockId> {
-        let filename = filename.into();
-        if self.l.lock().is_ok() {
-            let newblknum = self.length(&filename)?;
-            let blk = BlockId::new(&filename, newblknum);
+    // the number of bytes read from the storage is returned.
+    // The slot is aligned for IoMode::Direct, the page it is decoded into need not be
+    fn read_slot(&self, blk: &BlockId, p: &mut Page) -> Result<usize> {
+        let mut slot = AlignedBuf::new(self.slotsize() as usize, self.alignment);
+        let read_len = self
+            .storage
+            .read_block(blk.filename(), blk.number(), &mut slot)?;
+
+        // a block past the end of the file reads as zeroes
+        if read_len == 0 {
+            for b in p.contents().iter_mut() {
+                *b = 0;
+            }
+            return Ok(read_len);
+        }
 
-            let b: Vec<u8> = vec![0; self.blocksize as usize];
+        let encoded = (self.blocksize + self.overhead()) as usize;
+        if read_len < slot.len() {
+            return Err(From::from(FileMgrError::Corrupted(blk.clone())));
+        }
 
-            let blocksize = self.blocksize;
-            let f = self.configure_file_table(blk.filename())?;
-            f.seek(SeekFrom::Start(blk.number() * blocksize))?;
-            f.write_all(&b)?;
+        let checksum = u32::from_be_bytes(slot[encoded..].try_into()?);
+        if checksum != crc32(&slot[..encoded]) {
+            return Err(From::from(FileMgrError::Corrupted(blk.clone())));
+        }
 
-            return Ok(blk);
+        if let Some(transform) = self.transform.as_ref() {
+            transform
+                .decode(blk, &mut slot[..encoded])
+                .map_err(|_| FileMgrError::Corrupted(blk.clone()))?;
         }
+        p.contents()
+            .copy_from_slice(&slot[..self.blocksize as usize]);
 
-        Err(From::from(FileMgrError::FileAccessFailed(filename)))
+        Ok(read_len)
     }
 
-    pub fn length(&mut self, filename: impl Into<String>) -> Result<u64> {
+    // give the block back, a later append of its file reuses it
+    pub fn free(&self, blk: &BlockId) -> Result<()> {
+        self.check_writable(blk.filename())?;
+        self.check_not_reserved(blk.filename())?;
+        if blk.number() >= self.length(blk.filename())? {
+            return Err(From::from(FileMgrError::BlockOutOfRange(blk.clone())));
+        }
+
+        let mut freelist = self.lock_freelist(blk.filename())?;
+        freelist.push(blk);
+        self.save_freelist(&freelist)
+    }
+
+    // shrink the file to its first nblocks blocks
+    pub fn truncate(&self, filename: impl Into<String>, nblocks: u64) -> Result<()> {
         let filename = filename.into();
-        let path = Path::new(&self.db_directory).join(&filename);
-        let md = fs::metadata(&path)?;
+        self.check_writable(&filename)?;
+        self.check_not_reserved(&filename)?;
 
-        // ceil
-        Ok((md.len() + self.blocksize - 1) / self.blocksize)
+        let mut freelist = self.lock_freelist(&filename)?;
+        self.truncate_blocks(&filename, nblocks)?;
+        freelist.truncate(&filename, nblocks);
+        self.save_freelist(&freelist)
     }
 
-    pub fn configure_file_table(
-        &mut self,
-        filename: impl Into<String>,
-    ) -> anyhow::Result<&mut std::fs::File> {
+    // remove the file together with its free blocks
+    pub fn delete(&self, filename: impl Into<String>) -> Result<()> {
         let filename = filename.into();
-        let path = Path::new(&self.db_directory).join(&filename);
-
-        let created_file = self.open_files.entry(filename).or_insert(
-            OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(&path)?,
+        self.check_writable(&filename)?;
+        self.check_not_reserved(&filename)?;
+
+        let mut freelist = self.lock_freelist(&filename)?;
+        match self.extents.as_ref() {
+            Some(extents) => extents.delete(self.storage.as_ref(), &filename)?,
+            None => self.storage.delete(&filename)?,
+        }
+        freelist.remove_file(&filename);
+        self.save_freelist(&freelist)?;
+
+        if let Ok(mut temp_files) = self.temp_files.lock() {
+            temp_files.remove(&filename);
+        }
+
+        Ok(())
+    }
+
+    // a name for a new temporary file, unique within this database
+    // usually obtained through TempFile, which deletes the file when dropped
+    pub fn new_temp_filename(&self) -> Result<String> {
+        self.check_writable(TEMP_PREFIX)?;
+        // temporary files left by earlier runs were removed at startup, so a counter is enough
+        let filename = format!(
+            "{}{}",
+            TEMP_PREFIX,
+            self.next_temp.fetch_add(1, Ordering::SeqCst)
         );
+        self.temp_files
+            .lock()
+            .map_err(|_| FileMgrError::FileAccessFailed(filename.clone()))?
+            .insert(filename.clone());
+
+        Ok(filename)
+    }
+
+    pub fn temp_filenames(&self) -> Vec<String> {
+        self.temp_files
+            .lock()
+            .map_or(vec![], |temp_files| temp_files.iter().cloned().collect())
+    }
+
+    pub fn length(&self, filename: impl Into<String>) -> Result<u64> {
+        let filename = filename.into();
+        match self.extents.as_ref() {
+            Some(extents) => extents.length(self.storage.as_ref(), &filename),
+            None => self.storage.length(&filename, self.slotsize()),
+        }
+    }
 
-        Ok(created_file)
+    // force the contents of the file to disk
+    pub fn sync(&self, filename: impl Into<String>) -> Result<()> {
+        let filename = filename.into();
+        if self.read_only {
+            return Ok(());
+        }
+        match self.extents.as_ref() {
+            Some(extents) => extents.sync(self.storage.as_ref(), &filename),
+            None => self.storage.sync(&filename),
+        }
+    }
+
+    // called by LogMgr after it writes out its log page
+    pub fn sync_log(&self, filename: impl Into<String>) -> Result<()> {
+        if self.durability == Durability::LogFlush {
+            self.sync(filename)?;
+        }
+
+        Ok(())
     }
 
     pub fn blocksize(&self) -> u64 {
         self.blocksize
     }
 
+    // a page of the block size. Pages need no alignment for IoMode::Direct: a block always travels
+    // with its checksum (and transform overhead), so read and write copy it through an aligned slot
+    pub fn new_page(&self) -> Page {
+        Page::new_from_size(self.blocksize as usize)
+    }
+
     pub fn is_new(&self) -> bool {
         self.is_new
     }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.extents.is_some()
+    }
+
+    // None if the storage backend keeps no file handles
+    pub fn handle_cache_stats(&self) -> Option<HandleCacheStats> {
+        self.storage.handle_cache_stats()
+    }
+
+    fn lock_freelist(&self, filename: &str) -> Result<MutexGuard<'_, FreeList>> {
+        Ok(self
+            .freelist
+            .lock()
+            .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?)
+    }
+
+    // synced whatever the durability policy: a free list lost in a crash leaks every block it held
+    fn save_freelist(&self, freelist: &FreeList) -> Result<()> {
+        let nblocks = freelist.save(self)?;
+        self.truncate_blocks(FREELIST_FILE, nblocks)?;
+        self.sync(FREELIST_FILE)
+    }
+
+    fn truncate_blocks(&self, filename: &str, nblocks: u64) -> Result<()> {
+        match self.extents.as_ref() {
+            Some(extents) => extents.truncate(self.storage.as_ref(), filename, nblocks),
+            None => self.storage.truncate(filename, nblocks, self.slotsize()),
+        }
+    }
+
+    // the number of bytes written to the storage is returned
+    fn write_contents(&self, blk: &BlockId, contents: &[u8]) -> Result<usize> {
+        match self.extents.as_ref() {
+            Some(extents) => {
+                let extent = self.encode_extent(blk, contents)?;
+                extents.write(self.storage.as_ref(), blk.filename(), blk.number(), &extent)?;
+                Ok(extent.len())
+            }
+            None => {
+                let slot = self.encode_slot(blk, contents)?;
+                self.storage
+                    .write_block(blk.filename(), blk.number(), &slot)?;
+                Ok(slot.len())
+            }
+        }
+    }
+
+    fn record_io(&self, filename: &str, op: IoOp, bytes: usize, elapsed: Duration) {
+        if let Ok(mut stats) = self.io_stats.lock() {
+            stats.record(filename, op, bytes as u64, elapsed);
+        }
+    }
+
+    fn read_extent(&self, extents: &ExtentMap, blk: &BlockId, p: &mut Page) -> Result<usize> {
+        let corrupted = || FileMgrError::Corrupted(blk.clone());
+        let mut extent = match extents.read(self.storage.as_ref(), blk.filename(), blk.number())? {
+            Some(extent) => extent,
+            None => {
+                for b in p.contents().iter_mut() {
+                    *b = 0;
+                }
+                return Ok(0);
+            }
+        };
+
+        let len = u32::from_be_bytes(extent[0..4].try_into()?) as usize;
+        let checksum = u32::from_be_bytes(extent[4..8].try_into()?);
+        let body = extent
+            .get_mut(EXTENT_HEADER_SIZE..EXTENT_HEADER_SIZE + len)
+            .filter(|body| body.len() > self.overhead() as usize)
+            .filter(|body| extent_checksum(blk, body) == checksum)
+            .ok_or_else(corrupted)?;
+        if let Some(transform) = self.transform.as_ref() {
+            transform.decode(blk, body).map_err(|_| corrupted())?;
+        }
+        let body_len = body.len() - self.overhead() as usize;
+        let body = &body[..body_len];
+
+        let blocksize = self.blocksize as usize;
+        match body[0] {
+            RAW if body.len() == blocksize + 1 => p.contents().copy_from_slice(&body[1..]),
+            LZ => {
+                let contents =
+                    compression::decompress(&body[1..], blocksize).ok_or_else(corrupted)?;
+                p.contents().copy_from_slice(&contents);
+            }
+            _ => return Err(From::from(corrupted())),
+        }
+
+        Ok(extent.len())
+    }
+
+    // | len | crc32 | method | data | transform overhead |
+    fn encode_extent(&self, blk: &BlockId, contents: &[u8]) -> Result<Vec<u8>> {
+        let compressed = compression::compress(contents);
+        let (method, data) = if compressed.len() < contents.len() {
+            (LZ, &compressed[..])
+        } else {
+            (RAW, contents)
+        };
+
+        let overhead = self.overhead() as usize;
+        let mut extent = Vec::with_capacity(EXTENT_HEADER_SIZE + 1 + data.len() + overhead);
+        extent.extend_from_slice(&[0; EXTENT_HEADER_SIZE]);
+        extent.push(method);
+        extent.extend_from_slice(data);
+        extent.resize(extent.len() + overhead, 0);
+
+        let body = &mut extent[EXTENT_HEADER_SIZE..];
+        if let Some(transform) = self.transform.as_ref() {
+            transform.encode(blk, body)?;
+        }
+        let header = [
+            (body.len() as u32).to_be_bytes(),
+            extent_checksum(blk, body).to_be_bytes(),
+        ]
+        .concat();
+        extent[..EXTENT_HEADER_SIZE].copy_from_slice(&header);
+
+        Ok(extent)
+    }
+
+    fn check_writable(&self, filename: &str) -> Result<()> {
+        if self.read_only {
+            return Err(From::from(FileMgrError::ReadOnly(filename.into())));
+        }
+
+        Ok(())
+    }
+
+    // the files FileMgr keeps for itself can't be freed, truncated or deleted by callers
+    fn check_not_reserved(&self, filename: &str) -> Result<()> {
+        if filename == SUPERBLOCK_FILE || filename == FREELIST_FILE {
+            return Err(From::from(FileMgrError::ReservedFile(filename.into())));
+        }
+
+        Ok(())
+    }
+
+    // the bytes the page transform adds to every block
+    fn overhead(&self) -> u64 {
+        self.transform.as_ref().map_or(0, |t| t.overhead() as u64)
+    }
+
+    // the number of bytes a block occupies in storage
+    fn slotsize(&self) -> u64 {
+        self.blocksize + self.overhead() + CHECKSUM_SIZE
+    }
+
+    // | contents | transform overhead | crc32 |
+    // the contents and the overhead as the page transform encodes them, the checksum covers both
+    fn encode_slot(&self, blk: &BlockId, contents: &[u8]) -> Result<AlignedBuf> {
+        let mut slot = AlignedBuf::new(self.slotsize() as usize, self.alignment);
+        slot[..self.blocksize as usize].copy_from_slice(contents);
+        let encoded = (self.blocksize + self.overhead()) as usize;
+        if let Some(transform) = self.transform.as_ref() {
+            transform.encode(blk, &mut slot[..encoded])?;
+        }
+        let checksum = crc32(&slot[..encoded]);
+        slot[encoded..].copy_from_slice(&checksum.to_be_bytes());
+
+        Ok(slot)
+    }
+}
+
+fn extent_checksum(blk: &BlockId, body: &[u8]) -> u32 {
+    let mut bytes = blk.filename().as_bytes().to_vec();
+    bytes.extend_from_slice(&blk.number().to_be_bytes());
+    bytes.extend_from_slice(body);
+
+    crc32(&bytes)
+}
+
+// an existing directory with files in it, but without a superblock
+fn holds_other_files(path: &Path) -> Result<bool> {
+    if !path.is_dir() {
+        return Ok(false);
+    }
+
+    let mut other_files = false;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name();
+        if name == SUPERBLOCK_FILE {
+            return Ok(false);
+        }
+        other_files |= name != LOCK_FILE;
+    }
+
+    Ok(other_files)
 }
@@ -0,0 +1,555 @@
+use super::filemanager::{FileMgrError, FileMgrOptions};
+use super::storage::{HandleCacheStats, Storage};
+
+use anyhow::Result;
+use fs2::FileExt;
+use memmap2::{Mmap, MmapMut};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, RwLockWriteGuard};
+
+// opening a database directory locks this file, so two processes never write the same database
+pub const LOCK_FILE: &str = "simpledb.lock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoMode {
+    // positional read/write system calls
+    Standard,
+    // serve reads and writes from a memory mapping of each file, for read-heavy workloads.
+    // A block is copied into the mapping only when FileMgr::write is called, so the
+    // write-ahead order kept by Buffer::flush (log first, then the block) still holds.
+    Mmap,
+    // bypass the OS page cache with O_DIRECT (Linux only), as BufferMgr already caches blocks.
+    // Blocks must fill whole multiples of FileMgrOptions::direct_io_alignment on disk.
+    Direct,
+}
+
+// keeps each file of the database as a file in db_directory.
+// Blocks are accessed with positional reads/writes, so no seek position is shared
+// and accesses to different blocks of the same file don't wait on each other.
+pub struct FileStorage {
+    db_directory: String,
+    // held (and locked) as long as the storage is open:
+    // exclusively by a writable storage, shared by read-only ones
+    _lock: File,
+    open_files: Mutex<HandleCache>,
+    // in IoMode::Mmap, the mapping of each file, shared by all handles of the file.
+    // It outlives an evicted handle that a thread may still be reading through, so a
+    // truncate through a reopened handle waits for that reader as well
+    mappings: Mutex<HashMap<String, SharedMapping>>,
+    // serializes appends so that two appenders never get the same block number
+    append_lock: Mutex<()>,
+    io_mode: IoMode,
+    direct_io_alignment: usize,
+    // open every file read-only and refuse anything that would change the directory
+    read_only: bool,
+}
+
+struct OpenFile {
+    file: File,
+    // in IoMode::Mmap, the whole file mapped into memory (None while it is empty)
+    map: SharedMapping,
+    // in IoMode::Direct, a second handle without O_DIRECT for requests that aren't aligned
+    // (e.g. the superblock), opened when first needed
+    buffered: OnceLock<File>,
+    path: PathBuf,
+    writable: bool,
+}
+
+type SharedMapping = Arc<RwLock<Option<Mapping>>>;
+
+// a read-only storage maps its files read-only
+enum Mapping {
+    ReadWrite(MmapMut),
+    ReadOnly(Mmap),
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Mapping::ReadWrite(m) => m,
+            Mapping::ReadOnly(m) => m,
+        }
+    }
+}
+
+impl OpenFile {
+    fn buffered(&self) -> io::Result<&File> {
+        if let Some(f) = self.buffered.get() {
+            return Ok(f);
+        }
+        let f = OpenOptions::new()
+            .read(true)
+            .write(self.writable)
+            .open(&self.path)?;
+
+        Ok(self.buffered.get_or_init(|| f))
+    }
+    // (re)map the file after its length changed
+    fn remap(&self, map: &mut Option<Mapping>) -> Result<()> {
+        // drop the old mapping first, it may reach past a truncated end
+        *map = None;
+        if self.file.metadata()?.len() > 0 {
+            // SAFETY: the directory is locked, so only this storage changes the file. Every
+            // handle of the file shares this lock, and the file is never shrunk without
+            // holding it for writing, so no mapping of the file is read past its end.
+            // A read-only storage shares the directory lock with other readers only
+            *map = Some(unsafe {
+                if self.writable {
+                    Mapping::ReadWrite(MmapMut::map_mut(&self.file)?)
+                } else {
+                    Mapping::ReadOnly(Mmap::map(&self.file)?)
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// open file handles, at most `capacity` of them.
+// The least recently used handle is closed to make room, and reopened when it is needed again.
+struct HandleCache {
+    capacity: usize,
+    // filename -> (handle, tick of its last use)
+    handles: HashMap<String, (Arc<OpenFile>, u64)>,
+    tick: u64,
+    stats: HandleCacheStats,
+}
+
+impl HandleCache {
+    fn new(capacity: usize) -> HandleCache {
+        HandleCache {
+            capacity: capacity.max(1),
+            handles: HashMap::new(),
+            tick: 0,
+            stats: HandleCacheStats::default(),
+        }
+    }
+
+    fn get(&mut self, filename: &str) -> Option<Arc<OpenFile>> {
+        self.tick += 1;
+        match self.handles.get_mut(filename) {
+            Some((f, last_used)) => {
+                *last_used = self.tick;
+                self.stats.hits += 1;
+                Some(Arc::clone(f))
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, filename: &str, f: Arc<OpenFile>) {
+        while self.handles.len() >= self.capacity {
+            let lru = self
+                .handles
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(name, _)| name.clone());
+
+            if let Some(name) = lru {
+                // a thread still using the handle keeps it open until it is done
+                self.handles.remove(&name);
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.handles.insert(filename.into(), (f, self.tick));
+    }
+
+    fn remove(&mut self, filename: &str) {
+        self.handles.remove(filename);
+    }
+
+    fn stats(&self) -> HandleCacheStats {
+        HandleCacheStats {
+            open: self.handles.len(),
+            ..self.stats
+        }
+    }
+}
+
+impl FileStorage {
+    pub fn new(db_directory: &str) -> Result<FileStorage> {
+        FileStorage::new_with_options(db_directory, &FileMgrOptions::default())
+    }
+
+    // uses the open file limit, the I/O mode and read-only mode of the options.
+    // Only read-only mode takes a shared lock, which keeps writers out but lets other readers in
+    pub fn new_with_options(db_directory: &str, options: &FileMgrOptions) -> Result<FileStorage> {
+        if options.io_mode == IoMode::Direct && !cfg!(target_os = "linux") {
+            return Err(From::from(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "direct I/O is only supported on Linux",
+            )));
+        }
+
+        let path = Path::new(db_directory);
+
+        if options.read_only {
+            if !path.is_dir() {
+                return Err(From::from(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no database directory: {}", db_directory),
+                )));
+            }
+        } else if !path.exists() {
+            fs::create_dir_all(path)?;
+        }
+
+        let lock = match OpenOptions::new()
+            .read(true)
+            .write(!options.read_only)
+            .create(!options.read_only)
+            .truncate(false)
+            .open(path.join(LOCK_FILE))
+        {
+            Ok(lock) => lock,
+            // every writable open leaves a lock file behind, so a directory without one
+            // was never a database, and a reader couldn't keep a later writer out of it
+            Err(e) if options.read_only && e.kind() == io::ErrorKind::NotFound => {
+                return Err(From::from(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no lock file in database directory: {}", db_directory),
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let locked = if options.read_only {
+            FileExt::try_lock_shared(&lock)
+        } else {
+            FileExt::try_lock_exclusive(&lock)
+        };
+        if let Err(e) = locked {
+            if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() {
+                return Err(From::from(FileMgrError::DatabaseInUse(db_directory.into())));
+            }
+            return Err(e.into());
+        }
+
+        Ok(FileStorage {
+            db_directory: String::from(db_directory),
+            _lock: lock,
+            open_files: Mutex::new(HandleCache::new(options.max_open_files)),
+            mappings: Mutex::new(HashMap::new()),
+            append_lock: Mutex::new(()),
+            io_mode: options.io_mode,
+            direct_io_alignment: options.direct_io_alignment,
+            read_only: options.read_only,
+        })
+    }
+
+    fn configure_file_table(&self, filename: &str) -> Result<Arc<OpenFile>> {
+        let mut open_files = self
+            .open_files
+            .lock()
+            .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?;
+
+        if let Some(f) = open_files.get(filename) {
+            return Ok(f);
+        }
+
+        let path = Path::new(&self.db_directory).join(filename);
+        let mut opts = OpenOptions::new();
+        opts.read(true)
+            .write(!self.read_only)
+            .create(!self.read_only)
+            .truncate(false);
+        #[cfg(target_os = "linux")]
+        if self.io_mode == IoMode::Direct {
+            std::os::unix::fs::OpenOptionsExt::custom_flags(&mut opts, libc::O_DIRECT);
+        }
+
+        let file = opts.open(&path)?;
+        let (map, mapped) = if self.io_mode == IoMode::Mmap {
+            let mut mappings = self
+                .mappings
+                .lock()
+                .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?;
+            match mappings.get(filename) {
+                // still up to date, as every length change remaps it
+                Some(map) => (Arc::clone(map), true),
+                None => {
+                    let map = Arc::new(RwLock::new(None));
+                    mappings.insert(filename.into(), Arc::clone(&map));
+                    (map, false)
+                }
+            }
+        } else {
+            (Arc::new(RwLock::new(None)), true)
+        };
+
+        let f = Arc::new(OpenFile {
+            file,
+            map,
+            buffered: OnceLock::new(),
+            path,
+            writable: !self.read_only,
+        });
+        if !mapped {
+            let mut map = lock_map(&f, filename)?;
+            f.remap(&mut map)?;
+        }
+        open_files.insert(filename, Arc::clone(&f));
+
+        Ok(f)
+    }
+
+    // a read-only storage can't create files, so one that doesn't exist is None (and reads as empty)
+    fn existing_file(&self, filename: &str) -> Result<Option<Arc<OpenFile>>> {
+        match self.configure_file_table(filename) {
+            Ok(f) => Ok(Some(f)),
+            Err(e)
+                if self.read_only
+                    && e.downcast_ref::<io::Error>()
+                        .is_some_and(|e| e.kind() == io::ErrorKind::NotFound) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn check_writable(&self, filename: &str) -> Result<()> {
+        if self.read_only {
+            return Err(From::from(FileMgrError::ReadOnly(filename.into())));
+        }
+
+        Ok(())
+    }
+
+    // the handle to transfer buf at offset with: O_DIRECT only takes aligned memory, offsets and lengths
+    fn handle_for<'a>(&self, f: &'a OpenFile, buf: &[u8], offset: usize) -> io::Result<&'a File> {
+        let align = self.direct_io_alignment;
+        if self.io_mode != IoMode::Direct
+            || ((buf.as_ptr() as usize).is_multiple_of(align)
+                && offset.is_multiple_of(align)
+                && buf.len().is_multiple_of(align))
+        {
+            return Ok(&f.file);
+        }
+
+        f.buffered()
+    }
+}
+
+fn lock_map<'a>(f: &'a OpenFile, filename: &str) -> Result<RwLockWriteGuard<'a, Option<Mapping>>> {
+    Ok(f.map
+        .write()
+        .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?)
+}
+
+impl Storage for FileStorage {
+    fn read_block(&self, filename: &str, blknum: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_at(filename, blknum * buf.len() as u64, buf)
+    }
+
+    fn write_block(&self, filename: &str, blknum: u64, buf: &[u8]) -> Result<()> {
+        self.write_at(filename, blknum * buf.len() as u64, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let f = match self.existing_file(filename)? {
+            Some(f) => f,
+            None => return Ok(0),
+        };
+        let offset = offset as usize;
+
+        if self.io_mode == IoMode::Mmap {
+            let map = f
+                .map
+                .read()
+                .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?;
+            let mapped = map.as_ref().map_or(&[][..], |m| &m[..]);
+
+            // the mapping covers the whole file, nothing lies past it
+            let start = offset.min(mapped.len());
+            let len = buf.len().min(mapped.len() - start);
+            buf[..len].copy_from_slice(&mapped[start..start + len]);
+
+            return Ok(len);
+        }
+
+        let handle = self.handle_for(&f, buf, offset)?;
+
+        Ok(read_full_at(handle, buf, offset as u64)?)
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<()> {
+        self.check_writable(filename)?;
+        let f = self.configure_file_table(filename)?;
+        let offset = offset as usize;
+
+        if self.io_mode == IoMode::Mmap {
+            let mut map = lock_map(&f, filename)?;
+            if let Some(Mapping::ReadWrite(m)) =
+                map.as_mut().filter(|m| offset + buf.len() <= m.len())
+            {
+                m[offset..offset + buf.len()].copy_from_slice(buf);
+                return Ok(());
+            }
+
+            // the write extends the file, so the mapping has to grow
+            write_all_at(&f.file, buf, offset as u64)?;
+            return f.remap(&mut map);
+        }
+
+        write_all_at(self.handle_for(&f, buf, offset)?, buf, offset as u64)?;
+
+        Ok(())
+    }
+
+    fn append_block(&self, filename: &str, buf: &[u8]) -> Result<u64> {
+        self.check_writable(filename)?;
+        let _guard = self
+            .append_lock
+            .lock()
+            .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?;
+
+        let blknum = self.length(filename, buf.len() as u64)?;
+        self.write_block(filename, blknum, buf)?;
+
+        Ok(blknum)
+    }
+
+    fn length(&self, filename: &str, blocksize: u64) -> Result<u64> {
+        let f = match self.existing_file(filename)? {
+            Some(f) => f,
+            None => return Ok(0),
+        };
+        let len = f.file.metadata()?.len();
+
+        // ceil
+        Ok(len.div_ceil(blocksize))
+    }
+
+    fn truncate(&self, filename: &str, nblocks: u64, blocksize: u64) -> Result<()> {
+        self.check_writable(filename)?;
+        let f = self.configure_file_table(filename)?;
+        let mut map = lock_map(&f, filename)?;
+        if f.file.metadata()?.len() > nblocks * blocksize {
+            *map = None;
+            f.file.set_len(nblocks * blocksize)?;
+            if self.io_mode == IoMode::Mmap {
+                f.remap(&mut map)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, filename: &str) -> Result<()> {
+        self.check_writable(filename)?;
+        if let Ok(mut open_files) = self.open_files.lock() {
+            open_files.remove(filename);
+        }
+        if let Ok(mut mappings) = self.mappings.lock() {
+            mappings.remove(filename);
+        }
+
+        let path = Path::new(&self.db_directory).join(filename);
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn sync(&self, filename: &str) -> Result<()> {
+        // nothing to sync without writes
+        if self.read_only {
+            return Ok(());
+        }
+
+        let f = self.configure_file_table(filename)?;
+        if let Some(Mapping::ReadWrite(m)) = lock_map(&f, filename)?.as_ref() {
+            m.flush()?;
+        }
+        f.file.sync_data()?;
+
+        Ok(())
+    }
+
+    fn filenames(&self) -> Result<Vec<String>> {
+        let mut filenames = vec![];
+        for entry in fs::read_dir(&self.db_directory)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            match entry.file_name().into_string() {
+                Ok(s) if s == LOCK_FILE => {}
+                Ok(s) => filenames.push(s),
+                Err(_) => return Err(From::from(FileMgrError::ParseFailed)),
+            }
+        }
+
+        Ok(filenames)
+    }
+
+    fn handle_cache_stats(&self) -> Option<HandleCacheStats> {
+        self.open_files
+            .lock()
+            .ok()
+            .map(|open_files| open_files.stats())
+    }
+}
+
+// read until buf is full or the end of the file is reached, returning the number of bytes read
+fn read_full_at(f: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut read_len = 0;
+    while read_len < buf.len() {
+        match read_at(f, &mut buf[read_len..], offset + read_len as u64) {
+            Ok(0) => break,
+            Ok(n) => read_len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(read_len)
+}
+
+fn write_all_at(f: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    let mut written = 0;
+    while written < buf.len() {
+        match write_at(f, &buf[written..], offset + written as u64) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn read_at(f: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(f, buf, offset)
+}
+
+#[cfg(unix)]
+fn write_at(f: &File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::write_at(f, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(f: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(f, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_at(f: &File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_write(f, buf, offset)
+}
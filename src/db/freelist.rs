@@ -0,0 +1,162 @@
+use super::blockid::BlockId;
+use super::checksum::crc32;
+use super::filemanager::FileMgr;
+
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryInto;
+
+// the file that persists the blocks freed by FileMgr::free
+pub const FREELIST_FILE: &str = "freelist";
+
+/*
+ * the free list is written as one byte string split over the blocks of FREELIST_FILE
+ *
+ * | len | crc32 | filename | count | blknum | blknum | ... | filename | count | ...
+ *   u32    u32   u32+bytes    u32     u64      u64
+ */
+const HEADER_SIZE: usize = 8;
+
+#[derive(Default)]
+pub struct FreeList {
+    files: HashMap<String, BTreeSet<u64>>,
+}
+
+impl FreeList {
+    // a damaged free list is dropped: that only leaks the blocks it held,
+    // while trusting it could hand out a block that is still in use
+    pub fn load(fm: &FileMgr) -> Result<FreeList> {
+        if fm.length(FREELIST_FILE)? == 0 {
+            return Ok(FreeList::default());
+        }
+
+        Ok(FreeList::read(fm).unwrap_or_default())
+    }
+
+    fn read(fm: &FileMgr) -> Result<FreeList> {
+        let blocksize = fm.blocksize() as usize;
+        let mut p = fm.new_page();
+        fm.read(&BlockId::new(FREELIST_FILE, 0), &mut p)?;
+
+        let len = u32::from_be_bytes(p.contents()[0..4].try_into()?) as usize;
+        let checksum = u32::from_be_bytes(p.contents()[4..8].try_into()?);
+
+        let mut bytes = p.contents().to_vec();
+        for blknum in 1..(HEADER_SIZE + len).div_ceil(blocksize) {
+            fm.read(&BlockId::new(FREELIST_FILE, blknum as u64), &mut p)?;
+            bytes.extend_from_slice(p.contents());
+        }
+
+        let body = bytes
+            .get(HEADER_SIZE..HEADER_SIZE + len)
+            .ok_or_else(|| anyhow::anyhow!("free list truncated"))?;
+        if crc32(body) != checksum {
+            return Err(anyhow::anyhow!("free list checksum mismatch"));
+        }
+
+        FreeList::decode(body)
+    }
+
+    fn decode(mut body: &[u8]) -> Result<FreeList> {
+        fn take<'a>(body: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+            if body.len() < n {
+                return Err(anyhow::anyhow!("free list truncated"));
+            }
+            let (head, tail) = body.split_at(n);
+            *body = tail;
+
+            Ok(head)
+        }
+
+        let mut files = HashMap::new();
+        while !body.is_empty() {
+            let namelen = u32::from_be_bytes(take(&mut body, 4)?.try_into()?) as usize;
+            let filename = String::from_utf8(take(&mut body, namelen)?.to_vec())?;
+            let count = u32::from_be_bytes(take(&mut body, 4)?.try_into()?);
+
+            let mut blocks = BTreeSet::new();
+            for _ in 0..count {
+                blocks.insert(u64::from_be_bytes(take(&mut body, 8)?.try_into()?));
+            }
+            files.insert(filename, blocks);
+        }
+
+        Ok(FreeList { files })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut body = vec![];
+        for (filename, blocks) in self.files.iter() {
+            body.extend_from_slice(&(filename.len() as u32).to_be_bytes());
+            body.extend_from_slice(filename.as_bytes());
+            body.extend_from_slice(&(blocks.len() as u32).to_be_bytes());
+            for blknum in blocks.iter() {
+                body.extend_from_slice(&blknum.to_be_bytes());
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + body.len());
+        bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&crc32(&body).to_be_bytes());
+        bytes.extend_from_slice(&body);
+
+        bytes
+    }
+
+    // rewrite FREELIST_FILE, returning the number of blocks it now needs
+    pub fn save(&self, fm: &FileMgr) -> Result<u64> {
+        let blocksize = fm.blocksize() as usize;
+        let bytes = self.encode();
+
+        let mut nblocks = 0;
+        for chunk in bytes.chunks(blocksize) {
+            let mut p = fm.new_page();
+            p.contents()[..chunk.len()].copy_from_slice(chunk);
+            fm.write(&BlockId::new(FREELIST_FILE, nblocks), &mut p)?;
+            nblocks += 1;
+        }
+
+        Ok(nblocks)
+    }
+
+    pub fn push(&mut self, blk: &BlockId) {
+        self.files
+            .entry(blk.filename().into())
+            .or_default()
+            .insert(blk.number());
+    }
+
+    // take the lowest free block of the file
+    pub fn pop(&mut self, filename: &str) -> Option<u64> {
+        let blocks = self.files.get_mut(filename)?;
+        let blknum = *blocks.iter().next()?;
+        blocks.remove(&blknum);
+        if blocks.is_empty() {
+            self.files.remove(filename);
+        }
+
+        Some(blknum)
+    }
+
+    // forget the free blocks at or past nblocks
+    pub fn truncate(&mut self, filename: &str, nblocks: u64) {
+        if let Some(blocks) = self.files.get_mut(filename) {
+            blocks.split_off(&nblocks);
+            if blocks.is_empty() {
+                self.files.remove(filename);
+            }
+        }
+    }
+
+    pub fn remove_file(&mut self, filename: &str) {
+        self.files.remove(filename);
+    }
+
+    // forget files that no longer exist (e.g. temporary files removed at startup)
+    pub fn retain_files(&mut self, filenames: &[String]) -> bool {
+        let before = self.files.len();
+        self.files.retain(|f, _| filenames.contains(f));
+
+        self.files.len() != before
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+// what FileMgr did with the blocks of a file (or of all files).
+// Bytes are counted as they reach the storage: with their checksums, and compressed if the
+// database is. The time includes the syncs that the durability policy adds to writes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IoCounters {
+    pub reads: u64,
+    pub writes: u64,
+    pub appends: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub read_time: Duration,
+    pub write_time: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoOp {
+    Read,
+    Write,
+    Append,
+}
+
+impl IoCounters {
+    fn add(&mut self, op: IoOp, bytes: u64, elapsed: Duration) {
+        match op {
+            IoOp::Read => {
+                self.reads += 1;
+                self.bytes_read += bytes;
+                self.read_time += elapsed;
+            }
+            IoOp::Write | IoOp::Append => {
+                if op == IoOp::Write {
+                    self.writes += 1;
+                } else {
+                    self.appends += 1;
+                }
+                self.bytes_written += bytes;
+                self.write_time += elapsed;
+            }
+        }
+    }
+}
+
+// a snapshot of the counters, taken with FileMgr::io_stats
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IoStats {
+    pub total: IoCounters,
+    pub files: HashMap<String, IoCounters>,
+}
+
+impl IoStats {
+    // the counters of one file, all zero if it hasn't been accessed
+    pub fn file(&self, filename: &str) -> IoCounters {
+        self.files.get(filename).copied().unwrap_or_default()
+    }
+
+    pub fn record(&mut self, filename: &str, op: IoOp, bytes: u64, elapsed: Duration) {
+        self.total.add(op, bytes, elapsed);
+        match self.files.get_mut(filename) {
+            Some(counters) => counters.add(op, bytes, elapsed),
+            None => {
+                let mut counters = IoCounters::default();
+                counters.add(op, bytes, elapsed);
+                self.files.insert(filename.into(), counters);
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use super::blockid::BlockId;
+use super::filemanager::FileMgr;
+use super::logmanager::{first_lsn, record_lsn, record_positions, Found, Fragments};
+use super::page::Page;
+
+use anyhow::Result;
+use std::sync::Arc;
+
+// walks the log from the oldest record to the newest, the way redo needs it.
+// Records are added back to front within a page, so each page is walked from its end
+pub struct LogForwardIterator {
+    fm: Arc<FileMgr>,
+    blk: BlockId,
+    // the last block of the log when the iterator was made
+    lastblk: u64,
+    p: Page,
+    // the records of the current page, oldest first
+    positions: Vec<usize>,
+    currentpos: usize,
+    // records with smaller LSNs are skipped
+    from_lsn: u64,
+    fragments: Fragments,
+}
+
+impl LogForwardIterator {
+    // start at the oldest record that begins in blk, lastblk is the last block visited
+    pub fn new(fm: Arc<FileMgr>, blk: BlockId, lastblk: BlockId) -> Result<LogForwardIterator> {
+        let mut iter = LogForwardIterator {
+            p: fm.new_page(),
+            fm,
+            blk,
+            lastblk: lastblk.number(),
+            positions: vec![],
+            currentpos: 0,
+            from_lsn: 0,
+            fragments: Fragments::new(false),
+        };
+        if iter.blk.number() <= iter.lastblk {
+            iter.load_page()?;
+        }
+
+        Ok(iter)
+    }
+
+    // start at the record with the LSN, or the first one after it
+    pub fn new_from_lsn(
+        fm: Arc<FileMgr>,
+        lastblk: BlockId,
+        lsn: u64,
+    ) -> Result<LogForwardIterator> {
+        // the first LSNs of the pages grow with the block number, and a record continued
+        // from the block before has the first LSN of its page.
+        // So the record begins in the last block starting before it, or in the one after that
+        let mut p = fm.new_page();
+        let (mut lo, mut hi) = (0, lastblk.number());
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            fm.read(&BlockId::new(lastblk.filename(), mid), &mut p)?;
+            let first = first_lsn(&p)?;
+            // a block that has never been written comes after every record
+            if first != 0 && first < lsn {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+
+        let mut iter = LogForwardIterator::new(
+            Arc::clone(&fm),
+            BlockId::new(lastblk.filename(), lo),
+            lastblk,
+        )?;
+        iter.from_lsn = lsn;
+
+        Ok(iter)
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.currentpos < self.positions.len() || self.blk.number() < self.lastblk
+    }
+
+    // like next(), but lends the record out of the iterator's page instead of copying it.
+    // The record can be used until the iterator moves on
+    pub fn next_record(&mut self) -> Option<(u64, &[u8])> {
+        let (lsn, found) = self.advance()?;
+        self.fragments.record(&self.p, found).map(|rec| (lsn, rec))
+    }
+
+    // move to the next record, returning its LSN. The fragments of a record come from its FIRST to its LAST,
+    // so the rest of a record begun before the first block and a record the log ends in the middle of are skipped
+    fn advance(&mut self) -> Option<(u64, Found)> {
+        loop {
+            let (lsn, pos) = self.next_fragment()?;
+            if lsn < self.from_lsn {
+                continue;
+            }
+            if let Some(found) = self.fragments.visit(&self.p, pos).ok()? {
+                return Some((lsn, found));
+            }
+        }
+    }
+
+    // move to the next record or fragment, returning its LSN and its position in the page
+    fn next_fragment(&mut self) -> Option<(u64, usize)> {
+        while self.currentpos == self.positions.len() {
+            if self.blk.number() >= self.lastblk {
+                return None;
+            }
+
+            self.blk = BlockId::new(self.blk.filename(), self.blk.number() + 1);
+            if self.load_page().is_err() {
+                return None;
+            }
+        }
+
+        let pos = self.positions[self.currentpos];
+        self.currentpos += 1;
+
+        Some((record_lsn(&self.p, pos).ok()?, pos))
+    }
+
+    fn load_page(&mut self) -> Result<()> {
+        self.fm.read(&self.blk, &mut self.p)?;
+        self.positions = record_positions(&self.p, self.fm.blocksize())?;
+        self.currentpos = 0;
+
+        Ok(())
+    }
+}
+
+impl Iterator for LogForwardIterator {
+    type Item = (u64, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (lsn, found) = self.advance()?;
+        self.fragments
+            .take_record(&self.p, found)
+            .map(|rec| (lsn, rec))
+    }
+}
@@ -1,26 +1,26 @@
 use super::blockid::BlockId;
 use super::filemanager::FileMgr;
+use super::logmanager::{record_data, scan_page, Found, Fragments, RECORD_OVERHEAD};
 use super::page::Page;
 
 use anyhow::Result;
-use std::cell::RefCell;
-use std::mem;
 use std::sync::Arc;
 
 pub struct LogIterator {
-    fm: Arc<RefCell<FileMgr>>,
+    fm: Arc<FileMgr>,
     blk: BlockId,
     p: Page,
     currentpos: u64,
     boundary: u64,
+    fragments: Fragments,
 }
 
 impl LogIterator {
-    pub fn new(fm: Arc<RefCell<FileMgr>>, blk: BlockId) -> Result<LogIterator> {
-        let mut p = Page::new_from_size(fm.borrow().blocksize() as usize);
+    pub fn new(fm: Arc<FileMgr>, blk: BlockId) -> Result<LogIterator> {
+        let mut p = fm.new_page();
 
-        fm.borrow_mut().read(&blk, &mut p)?;
-        let boundary = p.get_int(0)? as u64;
+        fm.read(&blk, &mut p)?;
+        let boundary = boundary(&p, fm.blocksize())?;
         let currentpos = boundary;
 
         Ok(LogIterator {
@@ -29,44 +29,75 @@ impl LogIterator {
             p,
             currentpos,
             boundary,
+            fragments: Fragments::new(true),
         })
     }
 
     pub fn has_next(&self) -> bool {
-        self.currentpos < self.fm.borrow().blocksize() || self.blk.number() > 0
+        self.currentpos < self.fm.blocksize() || self.blk.number() > 0
     }
-}
 
-impl Iterator for LogIterator {
-    type Item = Vec<u8>;
+    // like next(), but lends the record out of the iterator's page instead of copying it.
+    // The record can be used until the iterator moves on.
+    pub fn next_record(&mut self) -> Option<&[u8]> {
+        let found = self.advance()?;
+        self.fragments.record(&self.p, found)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    // move to the next record. The fragments of a record come newest first, from its LAST to its FIRST,
+    // so the ones of a record whose LAST never reached the disk are skipped
+    fn advance(&mut self) -> Option<Found> {
+        loop {
+            let pos = self.next_fragment()?;
+            if let Some(found) = self.fragments.visit(&self.p, pos).ok()? {
+                return Some(found);
+            }
+        }
+    }
+
+    // move to the next record or fragment, returning its position in the page
+    fn next_fragment(&mut self) -> Option<usize> {
         if !self.has_next() {
             return None;
         }
 
-        if self.currentpos == self.fm.borrow().blocksize() {
+        if self.currentpos == self.fm.blocksize() {
             self.blk = BlockId::new(self.blk.filename(), self.blk.number() - 1);
 
-            if self.fm.borrow_mut().read(&self.blk, &mut self.p).is_err() {
+            if self.fm.read(&self.blk, &mut self.p).is_err() {
                 return None;
             }
 
-            if let Ok(n) = self.p.get_int(0) {
-                self.boundary = n as u64;
+            if let Ok(n) = boundary(&self.p, self.fm.blocksize()) {
+                self.boundary = n;
                 self.currentpos = self.boundary;
             } else {
                 return None;
             }
         }
 
-        if let Ok(rec) = self.p.get_bytes_vec(self.currentpos as usize) {
-            let i32_size = mem::size_of::<i32>() as u64;
-            self.currentpos += i32_size + rec.len() as u64;
+        let pos = self.currentpos as usize;
+        if let Ok(rec) = record_data(&self.p, pos) {
+            self.currentpos += (RECORD_OVERHEAD + rec.len()) as u64;
 
-            return Some(rec);
+            return Some(pos);
         }
 
         None
     }
 }
+
+impl Iterator for LogIterator {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let found = self.advance()?;
+        self.fragments.take_record(&self.p, found)
+    }
+}
+
+// where the records of the page start.
+// Only the intact ones are returned, a never written block (e.g. the log of a read-only database) holds none
+fn boundary(p: &Page, blocksize: u64) -> Result<u64> {
+    Ok(scan_page(p, blocksize)?.0)
+}
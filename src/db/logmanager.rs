@@ -1,112 +1,683 @@
 use super::blockid::BlockId;
-use super::filemanager::FileMgr;
+use super::checksum::crc32;
+use super::filemanager::{FileMgr, FileMgrError};
+use super::logforwarditerator::LogForwardIterator;
 use super::logiterator::LogIterator;
 use super::page::Page;
 
 use anyhow::Result;
-use std::cell::RefCell;
+use std::fmt;
 use std::mem;
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/*
+ * log page
+ *
+ * | boundary | first lsn | blknum |   free   | rec n | ... | rec 2 | rec 1 |
+ *     i32         i64       i64
+ *
+ * records are added back to front, boundary is the offset of the newest one.
+ * first lsn is the LSN of rec 1, the records after it have the LSNs that follow
+ * (rec 1 may be the rest of a record begun in the block before).
+ * blknum is the block the page belongs to, which tells whose copy the shadow is
+ */
+pub const LOG_HEADER_SIZE: usize = 20;
+const FIRST_LSN_OFFSET: usize = 4;
+const BLKNUM_OFFSET: usize = 12;
+
+// the last block of the log has a copy in this file, and a flush writes whichever of the two
+// is older: one block write and one sync, which never overwrites the newest copy on disk.
+// A write torn by a crash leaves the other copy, so every record reported as saved survives.
+// Iterators read the block itself, so it is brought up to date before one starts,
+// and before the log moves on to a new block
+pub const SHADOW_SUFFIX: &str = ".shadow";
+
+/*
+ * log record
+ *
+ * | lsn | crc32 | kind | len | data | len |
+ *   i64    u32    i16   i32          i32
+ *
+ * the checksum covers the lsn, the kind and the data. The length is repeated at the end,
+ * so the records of a page can also be walked from the oldest one, without trusting boundary.
+ * A record that does not fit in the rest of the page is split into fragments over several pages,
+ * all with the LSN of the record: one FIRST, any number of MIDDLE and one LAST
+ */
+const RECORD_CRC_OFFSET: usize = 8;
+const RECORD_KIND_OFFSET: usize = 12;
+const RECORD_DATA_OFFSET: usize = 14;
+pub const RECORD_OVERHEAD: usize = 22;
+
+const FULL: i16 = 0;
+const FIRST: i16 = 1;
+const MIDDLE: i16 = 2;
+const LAST: i16 = 3;
+
+// the smallest FIRST fragment, a record that would start with less begins in a new block
+const MIN_FRAGMENT: usize = 16;
+
+// a fresh page must have room for a FIRST fragment, or a record would never be started
+const MIN_BLOCKSIZE: u64 = (LOG_HEADER_SIZE + RECORD_OVERHEAD + MIN_FRAGMENT) as u64;
+
+#[derive(Debug, PartialEq)]
+pub enum LogMgrError {
+    BlockTooSmall(u64),
+}
+
+impl std::error::Error for LogMgrError {}
+impl fmt::Display for LogMgrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogMgrError::BlockTooSmall(blocksize) => write!(
+                f,
+                "block size too small for the log: {}, at least {} needed",
+                blocksize, MIN_BLOCKSIZE
+            ),
+        }
+    }
+}
 
 pub struct LogMgr {
-    fm: Arc<RefCell<FileMgr>>,
+    fm: Arc<FileMgr>,
     logfile: String,
+    state: Mutex<LogState>,
+    // signalled when a flush has finished
+    flushed: Condvar,
+}
+
+struct LogState {
     logpage: Page,
     currentblk: BlockId,
+    // LSNs start at 1 and grow by one with every record, 0 is "no record"
     latest_lsn: u64,
     lastsaved_lsn: u64,
+    // a flush is writing the log page, without holding the lock
+    flushing: bool,
+    // whether the newest copy of the current block on disk is the block itself, else the shadow
+    home_current: bool,
 }
 
 impl LogMgr {
-    pub fn new(fm: Arc<RefCell<FileMgr>>, logfile: String) -> Result<LogMgr> {
-        let mut logpage = Page::new_from_size(fm.borrow().blocksize() as usize);
-        let logsize = fm.borrow_mut().length(logfile.clone())?;
+    pub fn new(fm: Arc<FileMgr>, logfile: String) -> Result<LogMgr> {
+        if fm.blocksize() < MIN_BLOCKSIZE {
+            return Err(From::from(LogMgrError::BlockTooSmall(fm.blocksize())));
+        }
 
-        let logmgr;
+        let mut logpage = fm.new_page();
+        let logsize = fm.length(logfile.clone())?;
 
-        if logsize == 0 {
-            let blk = fm.borrow_mut().append(&logfile)?;
-            logpage.set_int(0, fm.borrow().blocksize() as i32)?;
-            fm.borrow_mut().write(&blk, &mut logpage)?;
+        let currentblk = if logsize == 0 && fm.is_read_only() {
+            // an empty log stays empty, the first block only exists in memory
+            init_page(&mut logpage, fm.blocksize(), 1, 0)?;
 
-            logmgr = LogMgr {
-                fm,
-                logfile,
-                logpage,
-                currentblk: blk,
-                latest_lsn: 0,
-                lastsaved_lsn: 0,
-            };
+            BlockId::new(&logfile, 0)
+        } else if logsize == 0 {
+            let blk = fm.append(&logfile)?;
+            init_page(&mut logpage, fm.blocksize(), 1, 0)?;
+            // a shadow left behind by an earlier log must not be taken for this one's
+            write_both(&fm, &blk, &mut logpage)?;
+
+            blk
         } else {
             let newblk = BlockId::new(&logfile, logsize - 1);
-            fm.borrow_mut().read(&newblk, &mut logpage)?;
+            let home = read_copy(&fm, &newblk, &newblk, &mut logpage)?;
+            let mut copy = fm.new_page();
+            let shadow = read_copy(&fm, &shadow_block(&logfile), &newblk, &mut copy)?;
 
-            logmgr = LogMgr {
-                fm,
-                logfile,
-                logpage,
-                currentblk: newblk,
-                latest_lsn: 0,
-                lastsaved_lsn: 0,
-            };
+            // the newer of the block and its copy, a torn write spoiled at most one of them
+            if shadow && (!home || newer(&copy, &logpage, fm.blocksize())?) {
+                logpage.contents().copy_from_slice(copy.contents());
+                if !fm.is_read_only() {
+                    write_block(&fm, &newblk, &mut logpage)?;
+                }
+            }
+
+            if home || shadow {
+                truncate_torn_tail(&fm, &newblk, &mut logpage)?;
+            } else {
+                // the block was appended but never written (a crash came in between),
+                // or its first write was torn: either way no flushed record is in it
+                let first_lsn = if logsize > 1 {
+                    let mut prev = fm.new_page();
+                    fm.read(&BlockId::new(&logfile, logsize - 2), &mut prev)?;
+                    scan_page(&prev, fm.blocksize())?.1
+                } else {
+                    1
+                };
+                init_page(&mut logpage, fm.blocksize(), first_lsn, newblk.number())?;
+                if !fm.is_read_only() {
+                    write_block(&fm, &newblk, &mut logpage)?;
+                }
+            }
+
+            drop_unfinished_record(&fm, &newblk, &mut logpage)?
         };
 
-        Ok(logmgr)
+        // continue after the newest record in the log
+        let latest_lsn = scan_page(&logpage, fm.blocksize())?.1 - 1;
+
+        Ok(LogMgr {
+            fm,
+            logfile,
+            state: Mutex::new(LogState {
+                logpage,
+                currentblk,
+                latest_lsn,
+                lastsaved_lsn: latest_lsn,
+                flushing: false,
+                home_current: true,
+            }),
+            flushed: Condvar::new(),
+        })
     }
 
     // ブロック内に空き容量があればログを追加, なければ新しいブロックを作成してログ追加
-    // TODO: implement thread safe func
-    pub fn append(&mut self, logrec: &mut Vec<u8>) -> Result<u64> {
-        let mut boundary = self.logpage.get_int(0)?;
-        let recsize = logrec.len() as i32;
-        let int32_size = mem::size_of::<i32>() as i32;
-        let bytesneeded = recsize + int32_size;
+    // a record larger than the free space is split, its fragments continue in new blocks.
+    // the LSN of the new record is returned
+    pub fn append(&self, logrec: &[u8]) -> Result<u64> {
+        if self.fm.is_read_only() {
+            return Err(From::from(FileMgrError::ReadOnly(self.logfile.clone())));
+        }
+
+        let mut state = self.lock()?;
+        let lsn = state.latest_lsn + 1;
+        let mut rest = logrec;
+        let mut first = true;
+        loop {
+            let boundary = state.logpage.get_int(0)? as usize;
+            let room = boundary.saturating_sub(LOG_HEADER_SIZE + RECORD_OVERHEAD);
 
-        if boundary - bytesneeded < int32_size {
-            self.flush()?;
+            if rest.len() <= room {
+                let kind = if first { FULL } else { LAST };
+                set_record(&mut state.logpage, boundary, lsn, kind, rest)?;
+                break;
+            }
+            // a fragment is only worth starting with some data in it
+            if room > 0 && (!first || room >= MIN_FRAGMENT) {
+                let kind = if first { FIRST } else { MIDDLE };
+                set_record(&mut state.logpage, boundary, lsn, kind, &rest[..room])?;
+                rest = &rest[room..];
+                first = false;
+            }
 
-            self.currentblk = self.append_newblk()?;
-            boundary = self.logpage.get_int(0)?;
+            // the full page goes to disk before the block is left behind,
+            // a flush still writing an older copy of it has to finish first
+            state = self.wait_flushed(state)?;
+            self.flush_home(&mut state)?;
+
+            state.currentblk = self.append_newblk(&mut state, lsn)?;
         }
+        state.latest_lsn = lsn;
 
-        let recpos = (boundary - bytesneeded) as usize;
-        self.logpage.set_bytes(recpos, logrec)?;
-        self.logpage.set_int(0, recpos as i32)?;
-        self.latest_lsn += 1;
+        Ok(lsn)
+    }
 
-        Ok(self.lastsaved_lsn)
+    // the LSN of the newest record, 0 if the log is empty
+    pub fn latest_lsn(&self) -> Result<u64> {
+        Ok(self.lock()?.latest_lsn)
     }
 
-    pub fn iterator(&mut self) -> Result<LogIterator> {
-        self.flush()?;
-        let iter = LogIterator::new(Arc::clone(&self.fm), self.currentblk.clone())?;
+    // the LSN of the newest record on disk
+    pub fn lastsaved_lsn(&self) -> Result<u64> {
+        Ok(self.lock()?.lastsaved_lsn)
+    }
+
+    pub fn iterator(&self) -> Result<LogIterator> {
+        let blk = self.flushed_block()?;
+        let iter = LogIterator::new(Arc::clone(&self.fm), blk)?;
 
         Ok(iter)
     }
 
-    pub fn flush_from_lsn(&mut self, lsn: u64) -> Result<()> {
-        if lsn > self.lastsaved_lsn {
-            self.flush()?;
+    // the records from the one with the LSN on, oldest first
+    pub fn forward_iterator(&self, lsn: u64) -> Result<LogForwardIterator> {
+        let blk = self.flushed_block()?;
+
+        LogForwardIterator::new_from_lsn(Arc::clone(&self.fm), blk, lsn)
+    }
+
+    // the records from the first one of the block on, oldest first
+    pub fn forward_iterator_from_block(&self, blknum: u64) -> Result<LogForwardIterator> {
+        let blk = self.flushed_block()?;
+
+        LogForwardIterator::new(
+            Arc::clone(&self.fm),
+            BlockId::new(blk.filename(), blknum),
+            blk,
+        )
+    }
+
+    // the record with the LSN, None if there is none
+    pub fn record(&self, lsn: u64) -> Result<Option<Vec<u8>>> {
+        if lsn == 0 || lsn > self.latest_lsn()? {
+            return Ok(None);
         }
 
-        Ok(())
+        Ok(self
+            .forward_iterator(lsn)?
+            .next()
+            .filter(|(found, _)| *found == lsn)
+            .map(|(_, rec)| rec))
+    }
+
+    // make sure the record with the LSN (and every one before it) is on disk.
+    // Concurrent callers are committed as a group: one of them writes and syncs the log page
+    // with the lock released, the others wait for it and find their records saved as well
+    pub fn flush_from_lsn(&self, lsn: u64) -> Result<()> {
+        let mut state = self.lock()?;
+        loop {
+            if lsn <= state.lastsaved_lsn || self.fm.is_read_only() {
+                return Ok(());
+            }
+            if !state.flushing {
+                break;
+            }
+            state = self
+                .flushed
+                .wait(state)
+                .map_err(|_| FileMgrError::FileAccessFailed(self.logfile.clone()))?;
+        }
+
+        // everything appended until now goes with this write
+        state.flushing = true;
+        let mut page = self.fm.new_page();
+        page.contents().copy_from_slice(state.logpage.contents());
+        let blk = self.older_copy(&state);
+        let upto = state.latest_lsn;
+        drop(state);
+
+        let written = write_block(&self.fm, &blk, &mut page);
+
+        let mut state = self.lock();
+        if let Ok(state) = state.as_mut() {
+            state.flushing = false;
+            if written.is_ok() {
+                state.lastsaved_lsn = state.lastsaved_lsn.max(upto);
+                state.home_current = !state.home_current;
+            }
+        }
+        self.flushed.notify_all();
+        drop(state);
+
+        written
     }
 
-    fn flush(&mut self) -> Result<()> {
-        self.fm
-            .borrow_mut()
-            .write(&self.currentblk, &mut self.logpage)?;
-        self.lastsaved_lsn = self.latest_lsn;
+    // write out the current block, which is the last one a forward iterator visits.
+    // Nothing is written if the block itself holds every record already
+    fn flushed_block(&self) -> Result<BlockId> {
+        let mut state = self.wait_flushed(self.lock()?)?;
+        if !state.home_current || state.lastsaved_lsn < state.latest_lsn {
+            self.flush_home(&mut state)?;
+        }
+
+        Ok(state.currentblk.clone())
+    }
+
+    // the copy of the current block a flush writes, never the newest one on disk
+    fn older_copy(&self, state: &LogState) -> BlockId {
+        if state.home_current {
+            shadow_block(&self.logfile)
+        } else {
+            state.currentblk.clone()
+        }
+    }
+
+    // bring the block itself up to date with the log page.
+    // Records only its newest copy holds go to the shadow first, so a torn write of the block loses none
+    fn flush_home(&self, state: &mut LogState) -> Result<()> {
+        // nothing can have been appended
+        if self.fm.is_read_only() {
+            return Ok(());
+        }
+
+        if state.home_current {
+            write_block(&self.fm, &shadow_block(&self.logfile), &mut state.logpage)?;
+            state.home_current = false;
+        }
+        write_block(&self.fm, &state.currentblk, &mut state.logpage)?;
+        state.home_current = true;
+        state.lastsaved_lsn = state.latest_lsn;
 
         Ok(())
     }
 
-    fn append_newblk(&mut self) -> Result<BlockId> {
-        let blk = self.fm.borrow_mut().append(&self.logfile)?;
-        self.logpage
-            .set_int(0, self.fm.borrow().blocksize() as i32)?;
-        self.fm.borrow_mut().write(&blk, &mut self.logpage)?;
+    // first_lsn is the LSN of the record that starts or continues in the new block
+    fn append_newblk(&self, state: &mut LogState, first_lsn: u64) -> Result<BlockId> {
+        let blk = self.fm.append(&self.logfile)?;
+        init_page(
+            &mut state.logpage,
+            self.fm.blocksize(),
+            first_lsn,
+            blk.number(),
+        )?;
+        write_block(&self.fm, &blk, &mut state.logpage)?;
+        state.home_current = true;
 
         Ok(blk)
     }
+
+    fn lock(&self) -> Result<MutexGuard<'_, LogState>> {
+        Ok(self
+            .state
+            .lock()
+            .map_err(|_| FileMgrError::FileAccessFailed(self.logfile.clone()))?)
+    }
+
+    fn wait_flushed<'a>(
+        &self,
+        mut state: MutexGuard<'a, LogState>,
+    ) -> Result<MutexGuard<'a, LogState>> {
+        while state.flushing {
+            state = self
+                .flushed
+                .wait(state)
+                .map_err(|_| FileMgrError::FileAccessFailed(self.logfile.clone()))?;
+        }
+
+        Ok(state)
+    }
+}
+
+// write a copy of the last log block, the block itself or its shadow
+fn write_block(fm: &FileMgr, blk: &BlockId, p: &mut Page) -> Result<()> {
+    fm.write(blk, p)?;
+    fm.sync_log(blk.filename())
+}
+
+// the shadow first: the block is only overwritten once its copy is on disk
+fn write_both(fm: &FileMgr, blk: &BlockId, p: &mut Page) -> Result<()> {
+    write_block(fm, &shadow_block(blk.filename()), p)?;
+    write_block(fm, blk, p)
+}
+
+fn shadow_block(logfile: &str) -> BlockId {
+    BlockId::new(format!("{}{}", logfile, SHADOW_SUFFIX), 0)
+}
+
+// read a copy of blk from the block itself or the shadow, false if it holds none:
+// it was torn, never written, or is the copy of another block
+fn read_copy(fm: &FileMgr, from: &BlockId, blk: &BlockId, p: &mut Page) -> Result<bool> {
+    if let Err(e) = fm.read(from, p) {
+        return match e.downcast_ref::<FileMgrError>() {
+            Some(FileMgrError::Corrupted(_)) => Ok(false),
+            _ => Err(e),
+        };
+    }
+
+    Ok(p.get_int(0)? != 0 && p.get_long(BLKNUM_OFFSET)? as u64 == blk.number())
+}
+
+// whether a holds more records than b, copies of the same block only ever grow
+fn newer(a: &Page, b: &Page, blocksize: u64) -> Result<bool> {
+    let (a_boundary, a_lsn) = scan_page(a, blocksize)?;
+    let (b_boundary, b_lsn) = scan_page(b, blocksize)?;
+
+    Ok((a_lsn, b_boundary) > (b_lsn, a_boundary))
+}
+
+fn init_page(p: &mut Page, blocksize: u64, first_lsn: u64, blknum: u64) -> Result<()> {
+    p.contents().fill(0);
+    p.set_int(0, blocksize as i32)?;
+    p.set_long(FIRST_LSN_OFFSET, first_lsn as i64)?;
+    p.set_long(BLKNUM_OFFSET, blknum as i64)?;
+
+    Ok(())
+}
+
+// drop the records after the last valid one, left behind by a write that did not complete
+fn truncate_torn_tail(fm: &FileMgr, blk: &BlockId, p: &mut Page) -> Result<()> {
+    let (boundary, _) = scan_page(p, fm.blocksize())?;
+    if p.get_int(0)? as u64 == boundary {
+        return Ok(());
+    }
+
+    p.contents()[LOG_HEADER_SIZE..boundary as usize].fill(0);
+    p.set_int(0, boundary as i32)?;
+    // a read-only log is repaired in memory only, its iterators skip the same records
+    if !fm.is_read_only() {
+        write_block(fm, blk, p)?;
+    }
+
+    Ok(())
+}
+
+// the record with its data ends at boundary, and becomes the newest one of the page
+fn set_record(p: &mut Page, boundary: usize, lsn: u64, kind: i16, data: &[u8]) -> Result<()> {
+    let pos = boundary - RECORD_OVERHEAD - data.len();
+    p.set_long(pos, lsn as i64)?;
+    p.set_int(
+        pos + RECORD_CRC_OFFSET,
+        record_checksum(lsn, kind, data) as i32,
+    )?;
+    p.set_short(pos + RECORD_KIND_OFFSET, kind)?;
+    let end = p.set_bytes(pos + RECORD_DATA_OFFSET, data)?;
+    p.set_int(end, data.len() as i32)?;
+    p.set_int(0, pos as i32)?;
+
+    Ok(())
+}
+
+// a record that spans blocks and whose last fragment never reached the disk is dropped,
+// together with the blocks after the one it begins in. The block the log goes on in is returned
+fn drop_unfinished_record(fm: &FileMgr, blk: &BlockId, p: &mut Page) -> Result<BlockId> {
+    let mut q = fm.new_page();
+    q.contents().copy_from_slice(p.contents());
+    let mut blknum = blk.number();
+    // the LSN of the unfinished record
+    let mut unfinished = None;
+
+    let start = loop {
+        let positions = record_positions(&q, fm.blocksize())?;
+        if let Some(lsn) = unfinished {
+            let first = positions.iter().position(|pos| {
+                record_kind(&q, *pos).ok() == Some(FIRST) && record_lsn(&q, *pos).ok() == Some(lsn)
+            });
+            if let Some(i) = first {
+                break i;
+            }
+        } else if let Some(pos) = positions.last() {
+            match record_kind(&q, *pos)? {
+                FIRST | MIDDLE => {
+                    unfinished = Some(record_lsn(&q, *pos)?);
+                    continue;
+                }
+                _ => return Ok(blk.clone()),
+            }
+        }
+
+        // an empty block, or one the record only continues in
+        if blknum == 0 {
+            return Ok(blk.clone());
+        }
+        blknum -= 1;
+        fm.read(&BlockId::new(blk.filename(), blknum), &mut q)?;
+    };
+
+    let positions = record_positions(&q, fm.blocksize())?;
+    let boundary = match start {
+        0 => fm.blocksize() as usize,
+        i => positions[i - 1],
+    };
+    q.contents()[LOG_HEADER_SIZE..boundary].fill(0);
+    q.set_int(0, boundary as i32)?;
+    p.contents().copy_from_slice(q.contents());
+
+    let newblk = BlockId::new(blk.filename(), blknum);
+    // a read-only log is repaired in memory only, its iterators skip the unfinished record.
+    // The block is rewritten once it is the last one, together with its shadow:
+    // the one of a dropped block must not be taken for a later block with its number
+    if !fm.is_read_only() {
+        if blknum < blk.number() {
+            fm.truncate(blk.filename(), blknum + 1)?;
+            write_both(fm, &newblk, p)?;
+        } else {
+            write_block(fm, &newblk, p)?;
+        }
+    }
+
+    Ok(newblk)
+}
+
+// the data of the record at pos
+pub(crate) fn record_data(p: &Page, pos: usize) -> Result<&[u8]> {
+    p.get_bytes(pos + RECORD_DATA_OFFSET)
+}
+
+pub(crate) fn record_lsn(p: &Page, pos: usize) -> Result<u64> {
+    Ok(p.get_long(pos)? as u64)
+}
+
+// FULL, or which fragment of a record it is
+pub(crate) fn record_kind(p: &Page, pos: usize) -> Result<i16> {
+    p.get_short(pos + RECORD_KIND_OFFSET)
+}
+
+// where a record the log iterators move to is
+pub(crate) enum Found {
+    InPage(usize),
+    Assembled,
+}
+
+// puts the records that span blocks back together from their fragments, for the log iterators.
+// They visit the fragments of a record in order (FIRST to LAST) or in reverse
+pub(crate) struct Fragments {
+    reverse: bool,
+    parts: Option<Vec<Vec<u8>>>,
+    // the last record put back together
+    record: Vec<u8>,
+}
+
+impl Fragments {
+    pub(crate) fn new(reverse: bool) -> Fragments {
+        Fragments {
+            reverse,
+            parts: None,
+            record: vec![],
+        }
+    }
+
+    // visit the record or fragment at pos, a record is found at its last fragment.
+    // The fragments of a record whose first one was never visited are skipped
+    pub(crate) fn visit(&mut self, p: &Page, pos: usize) -> Result<Option<Found>> {
+        let kind = record_kind(p, pos)?;
+        if kind == FULL {
+            self.parts = None;
+            return Ok(Some(Found::InPage(pos)));
+        }
+
+        let (start, end) = if self.reverse {
+            (LAST, FIRST)
+        } else {
+            (FIRST, LAST)
+        };
+        let data = record_data(p, pos)?.to_vec();
+        if kind == start {
+            self.parts = Some(vec![data]);
+        } else if let Some(parts) = self.parts.as_mut() {
+            parts.push(data);
+            if kind == end {
+                if self.reverse {
+                    parts.reverse();
+                }
+                self.record = parts.concat();
+                self.parts = None;
+
+                return Ok(Some(Found::Assembled));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // the record found, lent out of the page or the assembled record
+    pub(crate) fn record<'a>(&'a self, p: &'a Page, found: Found) -> Option<&'a [u8]> {
+        match found {
+            Found::InPage(pos) => record_data(p, pos).ok(),
+            Found::Assembled => Some(&self.record),
+        }
+    }
+
+    pub(crate) fn take_record(&mut self, p: &Page, found: Found) -> Option<Vec<u8>> {
+        match found {
+            Found::InPage(pos) => record_data(p, pos).ok().map(|rec| rec.to_vec()),
+            Found::Assembled => Some(mem::take(&mut self.record)),
+        }
+    }
+}
+
+// the record ending at end if it is intact and has the LSN, else None.
+// Its position and kind are returned
+fn valid_record(p: &Page, end: usize, lsn: u64) -> Option<(usize, i16)> {
+    let len = p.get_int(end.checked_sub(mem::size_of::<i32>())?).ok()?;
+    if len < 0 {
+        return None;
+    }
+    let start = end.checked_sub(RECORD_OVERHEAD + len as usize)?;
+    if start < LOG_HEADER_SIZE || p.get_long(start).ok()? as u64 != lsn {
+        return None;
+    }
+
+    let data = record_data(p, start).ok()?;
+    let kind = record_kind(p, start).ok()?;
+    let checksum = p.get_int(start + RECORD_CRC_OFFSET).ok()? as u32;
+    if data.len() != len as usize || checksum != record_checksum(lsn, kind, data) {
+        return None;
+    }
+
+    Some((start, kind))
+}
+
+// where the intact records of the page start, oldest first.
+// The walk stops at the first record that is not intact
+pub(crate) fn record_positions(p: &Page, blocksize: u64) -> Result<Vec<usize>> {
+    Ok(walk_page(p, blocksize)?.0)
+}
+
+// where the newest valid record of the page starts and the LSN the record after it gets,
+// which is the LSN of the newest one if only some of its fragments are in the page
+pub(crate) fn scan_page(p: &Page, blocksize: u64) -> Result<(u64, u64)> {
+    let (positions, lsn) = walk_page(p, blocksize)?;
+    let boundary = positions.last().map_or(blocksize, |pos| *pos as u64);
+
+    Ok((boundary, lsn))
+}
+
+fn walk_page(p: &Page, blocksize: u64) -> Result<(Vec<usize>, u64)> {
+    let mut lsn = first_lsn(p)?;
+    let mut pos = blocksize as usize;
+    let mut positions = vec![];
+    // whether the fragments of a record are being walked, unknown for the oldest one
+    let mut inside = None;
+    while let Some((start, kind)) = valid_record(p, pos, lsn) {
+        let follows = match kind {
+            FULL | FIRST => inside != Some(true),
+            MIDDLE | LAST => inside != Some(false),
+            _ => false,
+        };
+        if !follows {
+            break;
+        }
+
+        positions.push(start);
+        pos = start;
+        inside = Some(kind == FIRST || kind == MIDDLE);
+        if inside == Some(false) {
+            lsn += 1;
+        }
+    }
+
+    Ok((positions, lsn))
+}
+
+// the LSN of the oldest record of the page, 0 if the block has never been written
+pub(crate) fn first_lsn(p: &Page) -> Result<u64> {
+    Ok(p.get_long(FIRST_LSN_OFFSET)? as u64)
+}
+
+fn record_checksum(lsn: u64, kind: i16, data: &[u8]) -> u32 {
+    let mut bytes = lsn.to_be_bytes().to_vec();
+    bytes.extend_from_slice(&kind.to_be_bytes());
+    bytes.extend_from_slice(data);
+
+    crc32(&bytes)
 }
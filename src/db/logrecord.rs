@@ -1,6 +1,11 @@
+use super::buffermanager::BufferMgr;
+use super::checkpointrecord::CheckpointRecord;
+use super::commitrecord::CommitRecord;
 use super::page::Page;
+use super::rollbackrecord::RollbackRecord;
 use super::setintrecord::SetIntRecord;
 use super::setstringrecord::SetStringRecord;
+use super::startrecord::StartRecord;
 
 use std::fmt;
 
@@ -30,19 +35,69 @@ impl fmt::Display for LogRecordError {
 }
 
 pub enum LogRecord {
+    Checkpoint(CheckpointRecord),
+    Start(StartRecord),
+    Commit(CommitRecord),
+    Rollback(RollbackRecord),
     SetInt(SetIntRecord),
     SetString(SetStringRecord),
 }
 
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogRecord::Checkpoint(r) => r.fmt(f),
+            LogRecord::Start(r) => r.fmt(f),
+            LogRecord::Commit(r) => r.fmt(f),
+            LogRecord::Rollback(r) => r.fmt(f),
+            LogRecord::SetInt(r) => r.fmt(f),
+            LogRecord::SetString(r) => r.fmt(f),
+        }
+    }
+}
+
+impl LogRecord {
+    pub fn op(&self) -> i32 {
+        match self {
+            LogRecord::Checkpoint(r) => r.op(),
+            LogRecord::Start(r) => r.op(),
+            LogRecord::Commit(r) => r.op(),
+            LogRecord::Rollback(r) => r.op(),
+            LogRecord::SetInt(r) => r.op(),
+            LogRecord::SetString(r) => r.op(),
+        }
+    }
+
+    // -1 for a checkpoint, which belongs to no transaction
+    pub fn tx_number(&self) -> i32 {
+        match self {
+            LogRecord::Checkpoint(r) => r.tx_number(),
+            LogRecord::Start(r) => r.tx_number(),
+            LogRecord::Commit(r) => r.tx_number(),
+            LogRecord::Rollback(r) => r.tx_number(),
+            LogRecord::SetInt(r) => r.tx_number(),
+            LogRecord::SetString(r) => r.tx_number(),
+        }
+    }
+
+    // only the update records change a block, there is nothing to undo for the others
+    pub fn undo(&self, bm: &mut BufferMgr) -> Result<()> {
+        match self {
+            LogRecord::SetInt(r) => r.undo(bm),
+            LogRecord::SetString(r) => r.undo(bm),
+            _ => Ok(()),
+        }
+    }
+}
+
 pub fn create_logrecord(bytes: Vec<u8>) -> Result<LogRecord> {
     let p = Page::new_from_bytes(bytes);
 
-    // TODO: implement other log records
     match p.get_int(0)? {
-        //CHECKPOINT => {}
-        //START => {}
-        //COMMIT => {}
-        //ROLLBACK => {}
+        CHECKPOINT => Ok(LogRecord::Checkpoint(CheckpointRecord::new(&p)?)),
+        START => Ok(LogRecord::Start(StartRecord::new(&p)?)),
+        COMMIT => Ok(LogRecord::Commit(CommitRecord::new(&p)?)),
+        ROLLBACK => Ok(LogRecord::Rollback(RollbackRecord::new(&p)?)),
         SETINT => Ok(LogRecord::SetInt(SetIntRecord::new(&p)?)),
         SETSTRING => Ok(LogRecord::SetString(SetStringRecord::new(&p)?)),
         _ => Err(From::from(LogRecordError::UnknownRecord)),
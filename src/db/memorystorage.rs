@@ -0,0 +1,113 @@
+use super::filemanager::FileMgrError;
+use super::storage::Storage;
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// keeps every file in memory. Nothing touches the disk, which makes it suited
+// to hermetic tests and ephemeral caches.
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage {
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn with_files<T>(
+        &self,
+        filename: &str,
+        f: impl FnOnce(&mut HashMap<String, Vec<u8>>) -> T,
+    ) -> Result<T> {
+        let mut files = self
+            .files
+            .lock()
+            .map_err(|_| FileMgrError::FileAccessFailed(filename.into()))?;
+
+        Ok(f(&mut files))
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read_block(&self, filename: &str, blknum: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_at(filename, blknum * buf.len() as u64, buf)
+    }
+
+    fn write_block(&self, filename: &str, blknum: u64, buf: &[u8]) -> Result<()> {
+        self.write_at(filename, blknum * buf.len() as u64, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.with_files(filename, |files| {
+            let data = match files.get(filename) {
+                Some(data) => data,
+                None => return 0,
+            };
+
+            let offset = (offset as usize).min(data.len());
+            let len = buf.len().min(data.len() - offset);
+            buf[..len].copy_from_slice(&data[offset..offset + len]);
+
+            len
+        })
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<()> {
+        self.with_files(filename, |files| {
+            let data = files.entry(filename.into()).or_default();
+
+            let offset = offset as usize;
+            if data.len() < offset + buf.len() {
+                data.resize(offset + buf.len(), 0);
+            }
+            data[offset..offset + buf.len()].copy_from_slice(buf);
+        })
+    }
+
+    fn append_block(&self, filename: &str, buf: &[u8]) -> Result<u64> {
+        self.with_files(filename, |files| {
+            let data = files.entry(filename.into()).or_default();
+
+            let blknum = data.len().div_ceil(buf.len());
+            data.resize(blknum * buf.len(), 0);
+            data.extend_from_slice(buf);
+
+            blknum as u64
+        })
+    }
+
+    fn length(&self, filename: &str, blocksize: u64) -> Result<u64> {
+        self.with_files(filename, |files| {
+            files
+                .get(filename)
+                .map_or(0, |data| (data.len() as u64).div_ceil(blocksize))
+        })
+    }
+
+    fn truncate(&self, filename: &str, nblocks: u64, blocksize: u64) -> Result<()> {
+        self.with_files(filename, |files| {
+            if let Some(data) = files.get_mut(filename) {
+                data.truncate((nblocks * blocksize) as usize);
+            }
+        })
+    }
+
+    fn delete(&self, filename: &str) -> Result<()> {
+        self.with_files(filename, |files| {
+            files.remove(filename);
+        })
+    }
+
+    fn sync(&self, _filename: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn filenames(&self) -> Result<Vec<String>> {
+        self.with_files("", |files| files.keys().cloned().collect())
+    }
+}
@@ -1,12 +1,32 @@
+pub mod alignedbuf;
 pub mod blockid;
 pub mod buffer;
 pub mod buffermanager;
+pub mod checkpointrecord;
+pub mod checksum;
+pub mod commitrecord;
+pub mod compression;
 pub mod constants;
+pub mod encryption;
+pub mod extentmap;
+pub mod faultstorage;
 pub mod filemanager;
+pub mod filestorage;
+pub mod freelist;
+pub mod iostats;
+pub mod logforwarditerator;
 pub mod logiterator;
 pub mod logmanager;
 pub mod logrecord;
+pub mod memorystorage;
+pub mod overflow;
 pub mod page;
+pub mod pagetransform;
+pub mod rollbackrecord;
 pub mod setintrecord;
 pub mod setstringrecord;
 pub mod simpledb;
+pub mod startrecord;
+pub mod storage;
+pub mod superblock;
+pub mod tempfile;
@@ -0,0 +1,225 @@
+use super::blockid::BlockId;
+use super::buffermanager::BufferMgr;
+use super::filemanager::FileMgr;
+use super::page::Page;
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+
+// a value too large for its record is stored in a chain of overflow blocks,
+// and the record keeps only an OverflowRef in its place.
+// The chain is read and written through the buffer pool like any other block.
+
+/*
+ * overflow block
+ *
+ * | next | chunk len | chunk |
+ *   i64       i32
+ *
+ * next is the number of the following block of the chain, -1 in the last block
+ */
+const NO_NEXT: i64 = -1;
+const CHUNK_OFFSET: usize = mem::size_of::<i64>();
+
+/*
+ * reference kept inline, in place of a value written with Page::set_bytes
+ *
+ * | -1 | first block | len |
+ *   i32       i64       i64
+ *
+ * the -1 can't be the length of an inline value, which tells the two apart
+ */
+const REF_MARKER: i32 = -1;
+
+#[derive(Debug)]
+enum OverflowError {
+    BrokenChain(BlockId),
+}
+
+impl std::error::Error for OverflowError {}
+impl fmt::Display for OverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverflowError::BrokenChain(blk) => write!(f, "broken overflow chain at {}", blk),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverflowRef {
+    // the first block of the chain, in the file the chain was written to
+    pub first: u64,
+    // the length of the whole value
+    pub len: u64,
+}
+
+impl OverflowRef {
+    // the number of bytes the reference takes in a page
+    pub fn length() -> usize {
+        Page::int_length() + 2 * Page::long_length()
+    }
+
+    // the reference at offset, None if the value there is stored inline
+    pub fn get(p: &Page, offset: usize) -> Result<Option<OverflowRef>> {
+        if p.get_int(offset)? != REF_MARKER {
+            return Ok(None);
+        }
+        let first = p.get_long(offset + Page::int_length())?;
+        let len = p.get_long(offset + Page::int_length() + Page::long_length())?;
+
+        Ok(Some(OverflowRef {
+            first: first as u64,
+            len: len as u64,
+        }))
+    }
+
+    pub fn set(&self, p: &mut Page, offset: usize) -> Result<usize> {
+        let offset = p.set_int(offset, REF_MARKER)?;
+        let offset = p.set_long(offset, self.first as i64)?;
+
+        p.set_long(offset, self.len as i64)
+    }
+}
+
+// the number of value bytes one overflow block holds
+pub fn chunk_size(blocksize: u64) -> usize {
+    blocksize as usize - CHUNK_OFFSET - Page::int_length()
+}
+
+// write value as a chain of new blocks at the end of filename, modified by txnum.
+// The blocks reach the disk when the buffer pool flushes them
+pub fn write_chain(
+    fm: &FileMgr,
+    bm: &mut BufferMgr,
+    filename: &str,
+    value: &[u8],
+    txnum: i32,
+) -> Result<OverflowRef> {
+    let chunks: Vec<&[u8]> = if value.is_empty() {
+        vec![value]
+    } else {
+        value.chunks(chunk_size(fm.blocksize())).collect()
+    };
+    let blocks = chunks
+        .iter()
+        .map(|_| fm.append(filename))
+        .collect::<Result<Vec<BlockId>>>()?;
+
+    for (i, (blk, chunk)) in blocks.iter().zip(chunks).enumerate() {
+        let next = blocks.get(i + 1).map_or(NO_NEXT, |b| b.number() as i64);
+
+        let buff = bm.pin(blk)?;
+        let written = {
+            let mut b = buff.borrow_mut();
+            let p = b.contents();
+            let written = p
+                .set_long(0, next)
+                .and_then(|_| p.set_bytes(CHUNK_OFFSET, chunk));
+            b.set_modified(txnum, -1);
+            written
+        };
+        bm.unpin(buff)?;
+        written?;
+    }
+
+    Ok(OverflowRef {
+        first: blocks[0].number(),
+        len: value.len() as u64,
+    })
+}
+
+// put the value of the chain back together
+pub fn read_chain(
+    fm: &FileMgr,
+    bm: &mut BufferMgr,
+    filename: &str,
+    r: &OverflowRef,
+) -> Result<Vec<u8>> {
+    check_len(fm, filename, r)?;
+    let mut value = Vec::with_capacity(r.len as usize);
+    let mut blk = BlockId::new(filename, r.first);
+    // append may hand out a block freed meanwhile, so a chain can point backwards too,
+    // but it never visits a block twice
+    let mut visited = HashSet::new();
+
+    loop {
+        visited.insert(blk.number());
+        let buff = bm.pin(&blk)?;
+        let next = {
+            let mut b = buff.borrow_mut();
+            let p = b.contents();
+            p.get_bytes(CHUNK_OFFSET)
+                .map(|chunk| value.extend_from_slice(chunk))
+                .and_then(|_| p.get_long(0))
+        };
+        // unpinned even when the block turned out not to be part of a chain
+        bm.unpin(buff)?;
+        let next = next?;
+
+        if next == NO_NEXT || value.len() as u64 >= r.len {
+            break;
+        }
+        if next < 0 || visited.contains(&(next as u64)) {
+            return Err(From::from(OverflowError::BrokenChain(blk)));
+        }
+        blk = BlockId::new(filename, next as u64);
+    }
+
+    if value.len() as u64 != r.len {
+        return Err(From::from(OverflowError::BrokenChain(blk)));
+    }
+
+    Ok(value)
+}
+
+// give the blocks of the chain back to the free list of filename, for when the value is replaced or deleted.
+// Their buffers are dropped from the pool, so none of the blocks may be pinned.
+// The chain must not be read afterwards
+pub fn free_chain(fm: &FileMgr, bm: &mut BufferMgr, filename: &str, r: &OverflowRef) -> Result<()> {
+    check_len(fm, filename, r)?;
+    // an empty value still takes a block
+    let nblocks = r.len.div_ceil(chunk_size(fm.blocksize()) as u64).max(1);
+    let mut blocks = vec![];
+    let mut blk = BlockId::new(filename, r.first);
+
+    loop {
+        let buff = bm.pin(&blk)?;
+        let next = buff.borrow_mut().contents().get_long(0);
+        bm.unpin(buff)?;
+        let next = next?;
+        blocks.push(blk.clone());
+
+        if next == NO_NEXT {
+            break;
+        }
+        // the last block the value needs must end the chain
+        if next < 0
+            || blocks.len() as u64 >= nblocks
+            || blocks.iter().any(|b| b.number() == next as u64)
+        {
+            return Err(From::from(OverflowError::BrokenChain(blk)));
+        }
+        blk = BlockId::new(filename, next as u64);
+    }
+
+    for blk in blocks.iter() {
+        bm.discard(blk)?;
+        fm.free(blk)?;
+    }
+
+    Ok(())
+}
+
+// the length comes from disk: one the file couldn't hold is garbage, not something to allocate
+fn check_len(fm: &FileMgr, filename: &str, r: &OverflowRef) -> Result<()> {
+    let capacity = fm.length(filename)? * chunk_size(fm.blocksize()) as u64;
+    if r.len > capacity {
+        return Err(From::from(OverflowError::BrokenChain(BlockId::new(
+            filename, r.first,
+        ))));
+    }
+
+    Ok(())
+}
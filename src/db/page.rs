@@ -1,13 +1,15 @@
 use anyhow::Result;
 use itertools::izip;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::mem;
 use std::str;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 enum PageError {
     BufferSizeExceeded,
+    TimestampOutOfRange,
 }
 
 impl std::error::Error for PageError {}
@@ -15,10 +17,23 @@ impl fmt::Display for PageError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             PageError::BufferSizeExceeded => write!(f, "buffer size exceeded"),
+            PageError::TimestampOutOfRange => write!(f, "timestamp out of range"),
         }
     }
 }
 
+/*
+ * encodings of the values a page holds, all numbers are big endian
+ *
+ *   short     i16, 2 bytes two's complement
+ *   int       i32, 4 bytes two's complement
+ *   long      i64, 8 bytes two's complement
+ *   double    f64, 8 bytes IEEE 754 binary64
+ *   bool      1 byte, 0 is false, anything else is true
+ *   timestamp i64 microseconds since the UNIX epoch (negative before it)
+ *   bytes     int length followed by the bytes
+ *   string    bytes holding UTF-8
+ */
 pub struct Page {
     bb: Vec<u8>,
 }
@@ -34,53 +49,84 @@ impl Page {
         }
     }
 
-    pub fn get_int(&self, offset: usize) -> Result<i32> {
-        let i32_size = mem::size_of::<i32>();
+    pub fn get_short(&self, offset: usize) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.get_array(offset)?))
+    }
 
-        if offset + i32_size - 1 < self.bb.len() {
-            let bytes = &self.bb[offset..offset + i32_size];
-            Ok(i32::from_be_bytes((*bytes).try_into()?))
-        } else {
-            Err(PageError::BufferSizeExceeded.into())
-        }
+    pub fn set_short(&mut self, offset: usize, n: i16) -> Result<usize> {
+        self.set_slice(offset, &n.to_be_bytes())
+    }
+
+    pub fn get_int(&self, offset: usize) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.get_array(offset)?))
     }
 
     pub fn set_int(&mut self, offset: usize, n: i32) -> Result<usize> {
-        let bytes = n.to_be_bytes();
+        self.set_slice(offset, &n.to_be_bytes())
+    }
+
+    pub fn get_long(&self, offset: usize) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.get_array(offset)?))
+    }
 
-        if offset + bytes.len() - 1 < self.bb.len() {
-            for (b, added) in izip!(&mut self.bb[offset..offset + bytes.len()], &bytes) {
-                *b = *added;
-            }
+    pub fn set_long(&mut self, offset: usize, n: i64) -> Result<usize> {
+        self.set_slice(offset, &n.to_be_bytes())
+    }
 
-            Ok(offset + bytes.len())
+    pub fn get_double(&self, offset: usize) -> Result<f64> {
+        Ok(f64::from_be_bytes(self.get_array(offset)?))
+    }
+
+    pub fn set_double(&mut self, offset: usize, d: f64) -> Result<usize> {
+        self.set_slice(offset, &d.to_be_bytes())
+    }
+
+    pub fn get_bool(&self, offset: usize) -> Result<bool> {
+        let [b] = self.get_array(offset)?;
+
+        Ok(b != 0)
+    }
+
+    pub fn set_bool(&mut self, offset: usize, b: bool) -> Result<usize> {
+        self.set_slice(offset, &[b as u8])
+    }
+
+    pub fn get_timestamp(&self, offset: usize) -> Result<SystemTime> {
+        let micros = self.get_long(offset)?;
+        let d = Duration::from_micros(micros.unsigned_abs());
+        let t = if micros >= 0 {
+            UNIX_EPOCH.checked_add(d)
         } else {
-            Err(PageError::BufferSizeExceeded.into())
-        }
+            UNIX_EPOCH.checked_sub(d)
+        };
+
+        t.ok_or_else(|| From::from(PageError::TimestampOutOfRange))
+    }
+
+    // precision below a microsecond is lost
+    pub fn set_timestamp(&mut self, offset: usize, t: SystemTime) -> Result<usize> {
+        let micros = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_micros().try_into().ok(),
+            Err(e) => i64::try_from(e.duration().as_micros()).ok().map(|m| -m),
+        };
+        let micros = micros.ok_or(PageError::TimestampOutOfRange)?;
+
+        self.set_long(offset, micros)
     }
 
-    pub(crate) fn get_bytes(&self, offset: usize) -> Result<&[u8]> {
+    pub fn get_bytes(&self, offset: usize) -> Result<&[u8]> {
         let len = self.get_int(offset)? as usize;
         let new_offset = offset + mem::size_of::<i32>();
+        self.check_range(new_offset, len)?;
 
-        if new_offset + len - 1 < self.bb.len() {
-            Ok(&self.bb[new_offset..new_offset + len])
-        } else {
-            Err(PageError::BufferSizeExceeded.into())
-        }
+        Ok(&self.bb[new_offset..new_offset + len])
     }
 
-    pub(crate) fn set_bytes(&mut self, offset: usize, b: &[u8]) -> Result<usize> {
-        if offset + mem::size_of::<i32>() + b.len() - 1 < self.bb.len() {
-            let new_offset = self.set_int(offset, b.len() as i32)?;
-            for (p, added) in izip!(&mut self.bb[new_offset..new_offset + b.len()], b) {
-                *p = *added
-            }
+    pub fn set_bytes(&mut self, offset: usize, b: &[u8]) -> Result<usize> {
+        self.check_range(offset, Page::bytes_length(b.len()))?;
+        let new_offset = self.set_int(offset, b.len() as i32)?;
 
-            Ok(new_offset + b.len())
-        } else {
-            Err(PageError::BufferSizeExceeded.into())
-        }
+        self.set_slice(new_offset, b)
     }
 
     pub fn get_string(&self, offset: usize) -> Result<String> {
@@ -90,16 +136,51 @@ impl Page {
         Ok(s)
     }
 
-    pub fn set_string(&mut self, offset: usize, s: impl Into<String>) -> Result<usize> {
-        self.set_bytes(offset, s.into().as_bytes())
+    // borrows the string from the page instead of copying it like get_string
+    pub fn get_str(&self, offset: usize) -> Result<&str> {
+        Ok(str::from_utf8(self.get_bytes(offset)?)?)
+    }
+
+    pub fn set_string(&mut self, offset: usize, s: impl AsRef<str>) -> Result<usize> {
+        self.set_bytes(offset, s.as_ref().as_bytes())
+    }
+
+    // the number of bytes each value takes in a page
+    pub fn short_length() -> usize {
+        mem::size_of::<i16>()
+    }
+
+    pub fn int_length() -> usize {
+        mem::size_of::<i32>()
+    }
+
+    pub fn long_length() -> usize {
+        mem::size_of::<i64>()
+    }
+
+    pub fn double_length() -> usize {
+        mem::size_of::<f64>()
+    }
+
+    pub fn bool_length() -> usize {
+        mem::size_of::<u8>()
     }
 
+    pub fn timestamp_length() -> usize {
+        mem::size_of::<i64>()
+    }
+
+    pub fn bytes_length(len: usize) -> usize {
+        mem::size_of::<i32>() + len
+    }
+
+    // strlen is the length of the string in bytes
     pub fn max_length(strlen: usize) -> usize {
         mem::size_of::<i32>() + (strlen * mem::size_of::<u8>())
     }
 
     // needed by FileMgr
-    pub fn contents(&mut self) -> &mut Vec<u8> {
+    pub fn contents(&mut self) -> &mut [u8] {
         &mut self.bb
     }
 
@@ -108,14 +189,26 @@ impl Page {
         str::from_utf8(self.contents()).unwrap()
     }
 
-    pub(crate) fn get_bytes_vec(&self, offset: usize) -> Result<Vec<u8>> {
-        let len = self.get_int(offset)? as usize;
-        let new_offset = offset + mem::size_of::<i32>();
+    // fail unless len bytes starting at offset lie inside the page
+    fn check_range(&self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.bb.len() => Ok(()),
+            _ => Err(PageError::BufferSizeExceeded.into()),
+        }
+    }
 
-        if new_offset + len - 1 < self.bb.len() {
-            Ok(self.bb[new_offset..new_offset + len].try_into()?)
-        } else {
-            Err(PageError::BufferSizeExceeded.into())
+    fn get_array<const N: usize>(&self, offset: usize) -> Result<[u8; N]> {
+        self.check_range(offset, N)?;
+
+        Ok(self.bb[offset..offset + N].try_into()?)
+    }
+
+    fn set_slice(&mut self, offset: usize, bytes: &[u8]) -> Result<usize> {
+        self.check_range(offset, bytes.len())?;
+        for (b, added) in izip!(&mut self.bb[offset..offset + bytes.len()], bytes) {
+            *b = *added;
         }
+
+        Ok(offset + bytes.len())
     }
 }
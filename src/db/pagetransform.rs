@@ -0,0 +1,21 @@
+use super::blockid::BlockId;
+
+use anyhow::Result;
+
+// a transformation FileMgr applies to every block between Page and the storage, e.g. Encryption.
+// encode runs on write and append, decode on read. Both work in place: encode is handed the block
+// followed by overhead() spare bytes (e.g. for a nonce and a tag) and fills all of them, decode
+// gets them back and restores the block in front. Both may depend on the block, on nothing else.
+// The checksum of a block is taken over its encoded bytes, so damage is found before decode.
+pub trait PageTransform: Send + Sync {
+    // the bytes encode adds to every block
+    fn overhead(&self) -> usize;
+
+    // recorded in the superblock, it must differ between transforms (or keys)
+    fn check_value(&self) -> u64;
+
+    fn encode(&self, blk: &BlockId, buf: &mut [u8]) -> Result<()>;
+
+    // an error if the bytes were not encoded for this block (or with this key)
+    fn decode(&self, blk: &BlockId, buf: &mut [u8]) -> Result<()>;
+}
@@ -1,9 +1,9 @@
 use super::blockid::BlockId;
+use super::buffermanager::BufferMgr;
 use super::logmanager::LogMgr;
 use super::logrecord::SETINT;
 use super::page::Page;
 
-use std::cell::RefCell;
 use std::fmt;
 use std::mem;
 use std::sync::Arc;
@@ -27,7 +27,7 @@ impl fmt::Display for SetIntRecord {
     }
 }
 
-/**
+/*
  *          tpos   fpos            bpos     opos    vpos
  * | SetInt | txnum |   filename   | blknum | offset | val |
  *    int       int   int + stirng    int　　　int     int
@@ -62,8 +62,23 @@ impl SetIntRecord {
         self.txnum
     }
 
+    // put the old value back into the block, the change is not logged again
+    pub fn undo(&self, bm: &mut BufferMgr) -> Result<()> {
+        let buff = bm.pin(&self.blk)?;
+        let written = {
+            let mut b = buff.borrow_mut();
+            let written = b.contents().set_int(self.offset as usize, self.val);
+            b.set_modified(self.txnum, -1);
+            written
+        };
+        bm.unpin(buff)?;
+        written?;
+
+        Ok(())
+    }
+
     pub fn write_to_log(
-        lm: Arc<RefCell<LogMgr>>,
+        lm: Arc<LogMgr>,
         txnum: i32,
         blk: BlockId,
         offset: i32,
@@ -84,6 +99,6 @@ impl SetIntRecord {
         p.set_int(opos, offset)?;
         p.set_int(vpos, val)?;
 
-        lm.borrow_mut().append(p.contents())
+        lm.append(p.contents())
     }
 }
@@ -1,9 +1,9 @@
 use super::blockid::BlockId;
+use super::buffermanager::BufferMgr;
 use super::logmanager::LogMgr;
 use super::logrecord::SETSTRING;
 use super::page::Page;
 
-use std::cell::RefCell;
 use std::fmt;
 use std::mem;
 use std::sync::Arc;
@@ -27,7 +27,7 @@ impl fmt::Display for SetStringRecord {
     }
 }
 
-/**
+/*
  *            tpos   fpos            bpos     opos     vpos
  * | SetString | txnum |   filename   | blknum | offset |      val     |
  *      int       int    int + stirng    int      int     int + string
@@ -62,8 +62,23 @@ impl SetStringRecord {
         self.txnum
     }
 
+    // put the old value back into the block, the change is not logged again
+    pub fn undo(&self, bm: &mut BufferMgr) -> Result<()> {
+        let buff = bm.pin(&self.blk)?;
+        let written = {
+            let mut b = buff.borrow_mut();
+            let written = b.contents().set_string(self.offset as usize, &self.val);
+            b.set_modified(self.txnum, -1);
+            written
+        };
+        bm.unpin(buff)?;
+        written?;
+
+        Ok(())
+    }
+
     pub fn write_to_log(
-        lm: Arc<RefCell<LogMgr>>,
+        lm: Arc<LogMgr>,
         txnum: i32,
         blk: BlockId,
         offset: i32,
@@ -84,6 +99,6 @@ impl SetStringRecord {
         p.set_int(opos, offset)?;
         p.set_string(vpos, val)?;
 
-        lm.borrow_mut().append(p.contents())
+        lm.append(p.contents())
     }
 }
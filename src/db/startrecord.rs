@@ -0,0 +1,52 @@
+use super::logmanager::LogMgr;
+use super::logrecord::START;
+use super::page::Page;
+
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+pub struct StartRecord {
+    txnum: i32,
+}
+
+impl fmt::Display for StartRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<START {}>", self.txnum)
+    }
+}
+
+/*
+ *         tpos
+ * | Start | txnum |
+ *   int     int
+ **/
+impl StartRecord {
+    pub fn new(p: &Page) -> Result<StartRecord> {
+        let tpos = mem::size_of::<i32>();
+        let txnum = p.get_int(tpos)?;
+
+        Ok(StartRecord { txnum })
+    }
+
+    pub fn op(&self) -> i32 {
+        START
+    }
+
+    pub fn tx_number(&self) -> i32 {
+        self.txnum
+    }
+
+    pub fn write_to_log(lm: Arc<LogMgr>, txnum: i32) -> Result<u64> {
+        let tpos = mem::size_of::<i32>();
+        let reclen = tpos + mem::size_of::<i32>();
+
+        let mut p = Page::new_from_size(reclen);
+        p.set_int(0, START)?;
+        p.set_int(tpos, txnum)?;
+
+        lm.append(p.contents())
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HandleCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    // handles open right now
+    pub open: usize,
+}
+
+// where FileMgr keeps its blocks
+// blocks are addressed by file name and block number, the size of a block is the length of the buffer.
+// read_at/write_at address bytes instead, for records that don't fill fixed-size blocks (compressed extents).
+// implementations are shared between threads, so every method takes &self
+pub trait Storage: Send + Sync {
+    // read the block into buf and return the number of bytes found (0 if the block lies past the end of the file)
+    fn read_block(&self, filename: &str, blknum: u64, buf: &mut [u8]) -> Result<usize>;
+
+    // write buf as the block, creating the file if needed
+    fn write_block(&self, filename: &str, blknum: u64, buf: &[u8]) -> Result<()>;
+
+    // read into buf from the byte offset, returning the number of bytes found like read_block
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize>;
+
+    // write buf at the byte offset, creating the file if needed
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<()>;
+
+    // write buf as a new block at the end of the file and return its block number
+    fn append_block(&self, filename: &str, buf: &[u8]) -> Result<u64>;
+
+    // the number of blocks in the file, 0 if it does not exist
+    fn length(&self, filename: &str, blocksize: u64) -> Result<u64>;
+
+    // drop the blocks at or past nblocks
+    fn truncate(&self, filename: &str, nblocks: u64, blocksize: u64) -> Result<()>;
+
+    fn delete(&self, filename: &str) -> Result<()>;
+
+    // force the file's contents to stable storage
+    fn sync(&self, filename: &str) -> Result<()>;
+
+    fn filenames(&self) -> Result<Vec<String>>;
+
+    // counters of the open file handle cache, for backends that keep one
+    fn handle_cache_stats(&self) -> Option<HandleCacheStats> {
+        None
+    }
+}
+
+// lets a caller keep a handle on the backend it hands to FileMgr (e.g. to script faults in tests)
+impl<S: Storage + ?Sized> Storage for Arc<S> {
+    fn read_block(&self, filename: &str, blknum: u64, buf: &mut [u8]) -> Result<usize> {
+        (**self).read_block(filename, blknum, buf)
+    }
+
+    fn write_block(&self, filename: &str, blknum: u64, buf: &[u8]) -> Result<()> {
+        (**self).write_block(filename, blknum, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        (**self).read_at(filename, offset, buf)
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<()> {
+        (**self).write_at(filename, offset, buf)
+    }
+
+    fn append_block(&self, filename: &str, buf: &[u8]) -> Result<u64> {
+        (**self).append_block(filename, buf)
+    }
+
+    fn length(&self, filename: &str, blocksize: u64) -> Result<u64> {
+        (**self).length(filename, blocksize)
+    }
+
+    fn truncate(&self, filename: &str, nblocks: u64, blocksize: u64) -> Result<()> {
+        (**self).truncate(filename, nblocks, blocksize)
+    }
+
+    fn delete(&self, filename: &str) -> Result<()> {
+        (**self).delete(filename)
+    }
+
+    fn sync(&self, filename: &str) -> Result<()> {
+        (**self).sync(filename)
+    }
+
+    fn filenames(&self) -> Result<Vec<String>> {
+        (**self).filenames()
+    }
+
+    fn handle_cache_stats(&self) -> Option<HandleCacheStats> {
+        (**self).handle_cache_stats()
+    }
+}
@@ -0,0 +1,116 @@
+use super::checksum::crc32;
+use super::filemanager::FileMgrError;
+use super::storage::Storage;
+
+use anyhow::Result;
+use std::convert::TryInto;
+
+// the header file written when a database is created, checked every time it is opened
+pub const SUPERBLOCK_FILE: &str = "superblock";
+
+const MAGIC: u32 = 0x5344_4221; // "SDB!"
+
+// bump whenever the on-disk format changes
+pub const FORMAT_VERSION: u32 = 9;
+
+// the features a database was created with, every later open must use the same ones
+pub const FLAG_COMPRESSED: u32 = 1;
+pub const FLAG_TRANSFORMED: u32 = 2;
+
+const FEATURES: [(u32, &str); 2] = [
+    (FLAG_COMPRESSED, "compression"),
+    (FLAG_TRANSFORMED, "a page transform"),
+];
+
+/*
+ * | magic | version | blocksize | flags | transform check | crc32 |
+ *    u32      u32        u64       u32          u64          u32
+ *
+ * the transform check is the check value of the page transform, it tells a wrong key
+ */
+const SUPERBLOCK_SIZE: usize = 32;
+
+#[derive(Debug, PartialEq)]
+pub struct Superblock {
+    pub version: u32,
+    pub blocksize: u64,
+    pub flags: u32,
+    pub transform_check: u64,
+}
+
+impl Superblock {
+    pub fn new(blocksize: u64, flags: u32, transform_check: u64) -> Superblock {
+        Superblock {
+            version: FORMAT_VERSION,
+            blocksize,
+            flags,
+            transform_check,
+        }
+    }
+
+    pub fn read(storage: &dyn Storage) -> Result<Superblock> {
+        let mut buf = [0; SUPERBLOCK_SIZE];
+        let read_len = storage.read_block(SUPERBLOCK_FILE, 0, &mut buf)?;
+
+        let magic = u32::from_be_bytes(buf[0..4].try_into()?);
+        let version = u32::from_be_bytes(buf[4..8].try_into()?);
+        if read_len < 8 || magic != MAGIC {
+            return Err(From::from(FileMgrError::NotADatabase));
+        }
+        // older formats laid the superblock out differently
+        if version != FORMAT_VERSION {
+            return Err(From::from(FileMgrError::UnsupportedVersion(version)));
+        }
+
+        let checksum = u32::from_be_bytes(buf[28..32].try_into()?);
+        if read_len != SUPERBLOCK_SIZE || checksum != crc32(&buf[..28]) {
+            return Err(From::from(FileMgrError::NotADatabase));
+        }
+
+        Ok(Superblock {
+            version,
+            blocksize: u64::from_be_bytes(buf[8..16].try_into()?),
+            flags: u32::from_be_bytes(buf[16..20].try_into()?),
+            transform_check: u64::from_be_bytes(buf[20..28].try_into()?),
+        })
+    }
+
+    pub fn write(&self, storage: &dyn Storage) -> Result<()> {
+        let mut buf = Vec::with_capacity(SUPERBLOCK_SIZE);
+        buf.extend_from_slice(&MAGIC.to_be_bytes());
+        buf.extend_from_slice(&self.version.to_be_bytes());
+        buf.extend_from_slice(&self.blocksize.to_be_bytes());
+        buf.extend_from_slice(&self.flags.to_be_bytes());
+        buf.extend_from_slice(&self.transform_check.to_be_bytes());
+        buf.extend_from_slice(&crc32(&buf).to_be_bytes());
+
+        storage.write_block(SUPERBLOCK_FILE, 0, &buf)?;
+        storage.sync(SUPERBLOCK_FILE)
+    }
+
+    // refuse to open a database written with another format, block size or features
+    pub fn check(&self, blocksize: u64, flags: u32, transform_check: u64) -> Result<()> {
+        if self.version != FORMAT_VERSION {
+            return Err(From::from(FileMgrError::UnsupportedVersion(self.version)));
+        }
+        if self.blocksize != blocksize {
+            return Err(From::from(FileMgrError::BlockSizeMismatch {
+                stored: self.blocksize,
+                requested: blocksize,
+            }));
+        }
+        for (flag, feature) in FEATURES.iter() {
+            if self.flags & flag != flags & flag {
+                return Err(From::from(FileMgrError::FeatureMismatch {
+                    feature: String::from(*feature),
+                    stored: self.flags & flag != 0,
+                }));
+            }
+        }
+        if self.transform_check != transform_check {
+            return Err(From::from(FileMgrError::TransformMismatch));
+        }
+
+        Ok(())
+    }
+}
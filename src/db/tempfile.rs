@@ -0,0 +1,36 @@
+use super::blockid::BlockId;
+use super::filemanager::FileMgr;
+
+use anyhow::Result;
+use std::sync::Arc;
+
+// scratch file for sorts and materialized results.
+// The file is deleted when the TempFile is dropped; if the process dies first,
+// FileMgr::new removes it at the next startup.
+pub struct TempFile {
+    fm: Arc<FileMgr>,
+    filename: String,
+}
+
+impl TempFile {
+    pub fn new(fm: Arc<FileMgr>) -> Result<TempFile> {
+        let filename = fm.new_temp_filename()?;
+
+        Ok(TempFile { fm, filename })
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn block(&self, blknum: u64) -> BlockId {
+        BlockId::new(&self.filename, blknum)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // nothing to do on failure, the next startup cleans up
+        let _ = self.fm.delete(&self.filename);
+    }
+}
@@ -2,8 +2,27 @@ mod db;
 pub use db::blockid;
 pub use db::buffer;
 pub use db::buffermanager;
+pub use db::checkpointrecord;
+pub use db::commitrecord;
 pub use db::constants;
+pub use db::encryption;
+pub use db::faultstorage;
 pub use db::filemanager;
+pub use db::filestorage;
+pub use db::iostats;
+pub use db::logforwarditerator;
+pub use db::logiterator;
 pub use db::logmanager;
+pub use db::logrecord;
+pub use db::memorystorage;
+pub use db::overflow;
 pub use db::page;
+pub use db::pagetransform;
+pub use db::rollbackrecord;
+pub use db::setintrecord;
+pub use db::setstringrecord;
 pub use db::simpledb;
+pub use db::startrecord;
+pub use db::storage;
+pub use db::superblock;
+pub use db::tempfile;
@@ -1,18 +1,22 @@
 use simple_db::blockid::BlockId;
 use simple_db::buffermanager::BufferMgr;
-use simple_db::filemanager::FileMgr;
+use simple_db::filemanager::{FileMgr, FileMgrOptions};
 use simple_db::logmanager::LogMgr;
+use simple_db::memorystorage::MemoryStorage;
 
-use std::cell::RefCell;
 use std::sync::Arc;
 
 #[test]
 fn buffermgr_test() {
-    let fm = FileMgr::new("./buffertests", 400).unwrap();
-    let fmrc = Arc::new(RefCell::new(fm));
-    let fmrc2 = Arc::clone(&fmrc);
-    let lm = LogMgr::new(fmrc, String::from("bufferfile")).unwrap();
-    let mut bm = BufferMgr::new(fmrc2, Arc::new(RefCell::new(lm)), 3);
+    let fm = FileMgr::new_from_storage(
+        Box::new(MemoryStorage::new()),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+    let fm = Arc::new(fm);
+    let lm = LogMgr::new(Arc::clone(&fm), String::from("bufferfile")).unwrap();
+    let mut bm = BufferMgr::new(fm, Arc::new(lm), 3);
 
     println!("Available buffers: {}", bm.available());
 
@@ -62,3 +66,37 @@ fn buffermgr_test() {
     assert_eq!(n4.borrow().block().unwrap().number(), 1);
     assert_eq!(n5.borrow().block().unwrap().number(), 3);
 }
+
+#[test]
+fn test_flush_all() {
+    let fm = Arc::new(
+        FileMgr::new_from_storage(
+            Box::new(MemoryStorage::new()),
+            400,
+            FileMgrOptions::default(),
+        )
+        .unwrap(),
+    );
+    let lm = Arc::new(LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap());
+    let mut bm = BufferMgr::new(Arc::clone(&fm), Arc::clone(&lm), 3);
+
+    let blk1 = fm.append("datafile").unwrap();
+    let blk2 = fm.append("datafile").unwrap();
+    let lsn = lm.append(b"update").unwrap() as i64;
+    let buff1 = bm.pin(&blk1).unwrap();
+    buff1.borrow_mut().contents().set_int(0, 1).unwrap();
+    buff1.borrow_mut().set_modified(1, lsn);
+    let buff2 = bm.pin(&blk2).unwrap();
+    buff2.borrow_mut().contents().set_int(0, 2).unwrap();
+    buff2.borrow_mut().set_modified(2, lsn);
+
+    // only the blocks the transaction modified are written
+    bm.flush_all(1).unwrap();
+    let mut p = fm.new_page();
+    fm.read(&blk1, &mut p).unwrap();
+    assert_eq!(1, p.get_int(0).unwrap());
+    fm.read(&blk2, &mut p).unwrap();
+    assert_eq!(0, p.get_int(0).unwrap());
+    assert_eq!(-1, buff1.borrow().modifying_tx());
+    assert_eq!(2, buff2.borrow().modifying_tx());
+}
@@ -0,0 +1,156 @@
+use simple_db::blockid::BlockId;
+use simple_db::encryption::Encryption;
+use simple_db::filemanager::{FileMgr, FileMgrError, FileMgrOptions};
+use simple_db::logmanager::LogMgr;
+use simple_db::memorystorage::MemoryStorage;
+use simple_db::page::Page;
+use simple_db::storage::Storage;
+
+use std::sync::Arc;
+
+const SECRET: &str = "card number 4111 1111 1111 1111";
+
+fn open(
+    storage: &Arc<MemoryStorage>,
+    key: Option<[u8; 32]>,
+    compression: bool,
+) -> Result<FileMgr, anyhow::Error> {
+    let options = FileMgrOptions {
+        compression,
+        transform: key.map(|key| Box::new(Encryption::new(key)) as _),
+        ..Default::default()
+    };
+
+    FileMgr::new_from_storage(Box::new(Arc::clone(storage)), 400, options)
+}
+
+// the raw bytes of the file as the storage keeps them
+fn raw_contents(storage: &MemoryStorage, filename: &str) -> Vec<u8> {
+    let mut buf = vec![0; 64 * 1024];
+    let len = storage.read_at(filename, 0, &mut buf).unwrap();
+    buf.truncate(len);
+
+    buf
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|w| w == needle.as_bytes())
+}
+
+#[test]
+fn test_encryption() {
+    let storage = Arc::new(MemoryStorage::new());
+    let key = [7; 32];
+
+    let fm = Arc::new(open(&storage, Some(key), false).unwrap());
+    let blk = fm.append("accounts").unwrap();
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    p.set_string(20, SECRET).unwrap();
+    fm.write(&blk, &mut p).unwrap();
+
+    let lm = LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap();
+    let mut rec = Page::new_from_size(Page::max_length(SECRET.len()));
+    rec.set_string(0, SECRET).unwrap();
+    lm.append(rec.contents()).unwrap();
+    // iterating flushes the log page
+    let mut iter = lm.iterator().unwrap();
+    assert!(iter.next_record().is_some());
+    drop(iter);
+    drop(lm);
+    drop(fm);
+
+    // neither the block nor the log record can be read from the files
+    assert!(!contains(&raw_contents(&storage, "accounts"), SECRET));
+    assert!(!contains(&raw_contents(&storage, "logfile"), SECRET));
+
+    // with the key both come back
+    let fm = Arc::new(open(&storage, Some(key), false).unwrap());
+    fm.read(&blk, &mut p).unwrap();
+    assert_eq!(SECRET, p.get_str(20).unwrap());
+
+    let lm = LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap();
+    let mut iter = lm.iterator().unwrap();
+    let rec = iter.next_record().unwrap();
+    assert_eq!(
+        SECRET,
+        Page::new_from_bytes(rec.to_vec()).get_str(0).unwrap()
+    );
+    drop(iter);
+    drop(lm);
+    drop(fm);
+
+    // a wrong key or no key at all is refused
+    let err = open(&storage, Some([8; 32]), false).err().unwrap();
+    assert_eq!(
+        Some(&FileMgrError::TransformMismatch),
+        err.downcast_ref::<FileMgrError>()
+    );
+    let err = open(&storage, None, false).err().unwrap();
+    assert_eq!(
+        Some(&FileMgrError::FeatureMismatch {
+            feature: "a page transform".into(),
+            stored: true
+        }),
+        err.downcast_ref::<FileMgrError>()
+    );
+}
+
+#[test]
+fn test_encryption_with_compression() {
+    let storage = Arc::new(MemoryStorage::new());
+    let key = [42; 32];
+
+    let fm = open(&storage, Some(key), true).unwrap();
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    for i in 0..5 {
+        let blk = fm.append("compressed").unwrap();
+        p.set_string(0, SECRET).unwrap();
+        p.set_int(200, i).unwrap();
+        fm.write(&blk, &mut p).unwrap();
+    }
+    drop(fm);
+
+    let raw = raw_contents(&storage, "compressed");
+    assert!(!contains(&raw, SECRET));
+    // blocks are compressed before they are encrypted
+    assert!(raw.len() < 5 * 400 / 2, "{} bytes stored", raw.len());
+
+    let fm = open(&storage, Some(key), true).unwrap();
+    for i in 0..5 {
+        fm.read(&BlockId::new("compressed", i as u64), &mut p)
+            .unwrap();
+        assert_eq!(SECRET, p.get_str(0).unwrap());
+        assert_eq!(i, p.get_int(200).unwrap());
+    }
+}
+
+#[test]
+fn test_fresh_nonce() {
+    let storage = Arc::new(MemoryStorage::new());
+    let fm = open(&storage, Some([9; 32]), false).unwrap();
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    p.set_string(0, SECRET).unwrap();
+
+    // the same contents are stored differently every time they are written
+    let blk = fm.append("accounts").unwrap();
+    fm.write(&blk, &mut p).unwrap();
+    let first = raw_contents(&storage, "accounts");
+    fm.write(&blk, &mut p).unwrap();
+    let second = raw_contents(&storage, "accounts");
+    assert_eq!(first.len(), second.len());
+    assert_ne!(first, second);
+
+    // a block copied to another place (checksum and all) doesn't decode there
+    let other = fm.append("accounts").unwrap();
+    storage.write_block("accounts", 1, &second).unwrap();
+    let err = fm.read(&other, &mut p).unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::Corrupted(other)),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    fm.read(&blk, &mut p).unwrap();
+    assert_eq!(SECRET, p.get_str(0).unwrap());
+}
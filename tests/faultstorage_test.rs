@@ -0,0 +1,249 @@
+use simple_db::blockid::BlockId;
+use simple_db::faultstorage::{Fault, FaultError, FaultStorage};
+use simple_db::filemanager::{FileMgr, FileMgrError, FileMgrOptions};
+use simple_db::filestorage::FileStorage;
+use simple_db::page::Page;
+use simple_db::storage::Storage;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("simpledb-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    dir
+}
+
+fn open_faulty(dir: &Path) -> (FileMgr, Arc<FaultStorage>) {
+    let inner = FileStorage::new(dir.to_str().unwrap()).unwrap();
+    let storage = Arc::new(FaultStorage::new(Box::new(inner)));
+    let fm = FileMgr::new_from_storage(
+        Box::new(Arc::clone(&storage)),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+    // don't count the superblock written on creation
+    storage.clear();
+
+    (fm, storage)
+}
+
+fn write_int(fm: &FileMgr, blk: &BlockId, n: i32) -> anyhow::Result<()> {
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    p.set_int(0, n)?;
+    fm.write(blk, &mut p)
+}
+
+fn read_int(fm: &FileMgr, blk: &BlockId) -> anyhow::Result<i32> {
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    fm.read(blk, &mut p)?;
+    p.get_int(0)
+}
+
+#[test]
+fn test_crash_after_writes() {
+    let dir = test_dir("crash_after_writes");
+    let (fm, storage) = open_faulty(&dir);
+    storage.inject(Fault::CrashAfterWrites(3));
+
+    for i in 0..3 {
+        write_int(&fm, &BlockId::new("crashfile", i), 100 + i as i32).unwrap();
+    }
+    let err = write_int(&fm, &BlockId::new("crashfile", 3), 103).unwrap_err();
+    assert_eq!(Some(&FaultError::Crashed), err.downcast_ref::<FaultError>());
+    assert!(storage.crashed());
+    assert_eq!(4, storage.writes());
+
+    // nothing works after the crash
+    assert!(read_int(&fm, &BlockId::new("crashfile", 0)).is_err());
+    drop(fm);
+    drop(storage);
+
+    let fm = FileMgr::new(dir.to_str().unwrap(), 400).unwrap();
+    assert_eq!(3, fm.length("crashfile").unwrap());
+    for i in 0..3 {
+        let blk = BlockId::new("crashfile", i);
+        assert_eq!(100 + i as i32, read_int(&fm, &blk).unwrap());
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_torn_write() {
+    let dir = test_dir("torn_write");
+    let (fm, storage) = open_faulty(&dir);
+    let blk = BlockId::new("tornfile", 0);
+
+    write_int(&fm, &blk, 1).unwrap();
+    storage.inject(Fault::TornWrite { nth: 2, bytes: 100 });
+    assert!(write_int(&fm, &blk, 2).is_err());
+    drop(fm);
+    drop(storage);
+
+    let fm = FileMgr::new(dir.to_str().unwrap(), 400).unwrap();
+    let err = read_int(&fm, &blk).unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::Corrupted(blk)),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_no_space_and_read_error() {
+    let dir = test_dir("no_space");
+    let (fm, storage) = open_faulty(&dir);
+
+    storage.inject(Fault::NoSpace(String::from("fullfile")));
+    let err = fm.append("fullfile").unwrap_err();
+    assert_eq!(
+        Some(&FaultError::NoSpace(String::from("fullfile"))),
+        err.downcast_ref::<FaultError>()
+    );
+    assert_eq!(0, fm.append("otherfile").unwrap().number());
+
+    let blk = BlockId::new("otherfile", 0);
+    storage.inject(Fault::ReadError(blk.clone()));
+    let err = read_int(&fm, &blk).unwrap_err();
+    assert_eq!(
+        Some(&FaultError::ReadFailed(blk.clone())),
+        err.downcast_ref::<FaultError>()
+    );
+
+    storage.clear();
+    assert_eq!(0, read_int(&fm, &blk).unwrap());
+    assert!(!storage.crashed());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_read_error_in_extent() {
+    let dir = test_dir("read_error_at");
+    let inner = FileStorage::new(dir.to_str().unwrap()).unwrap();
+    let storage = Arc::new(FaultStorage::new(Box::new(inner)));
+    let options = FileMgrOptions {
+        compression: true,
+        ..Default::default()
+    };
+    let fm = FileMgr::new_from_storage(Box::new(Arc::clone(&storage)), 400, options).unwrap();
+
+    // the blocks of a compressed file are read as extents, not whole blocks
+    let blk = BlockId::new("packedfile", 0);
+    write_int(&fm, &blk, 7).unwrap();
+    storage.inject(Fault::ReadErrorAt {
+        filename: String::from("packedfile"),
+        offset: 0,
+    });
+    let err = read_int(&fm, &blk).unwrap_err();
+    assert_eq!(
+        Some(&FaultError::ReadFailedAt {
+            filename: String::from("packedfile"),
+            offset: 0
+        }),
+        err.downcast_ref::<FaultError>()
+    );
+
+    storage.clear();
+    assert_eq!(7, read_int(&fm, &blk).unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_volatile_writes() {
+    let dir = test_dir("volatile_writes");
+    let inner = FileStorage::new(dir.to_str().unwrap()).unwrap();
+    let storage = Arc::new(FaultStorage::new_with_volatile_writes(Box::new(inner)));
+    let fm = FileMgr::new_from_storage(
+        Box::new(Arc::clone(&storage)),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+    let logblk = BlockId::new("logfile", 0);
+    let datablk = BlockId::new("datafile", 0);
+
+    // the log is synced when it is flushed, data blocks are not
+    write_int(&fm, &logblk, 1).unwrap();
+    fm.sync_log("logfile").unwrap();
+    write_int(&fm, &datablk, 2).unwrap();
+    assert_eq!(2, read_int(&fm, &datablk).unwrap());
+    assert_eq!(1, fm.length("datafile").unwrap());
+
+    storage.inject(Fault::CrashAfterWrites(storage.writes()));
+    assert!(write_int(&fm, &datablk, 3).is_err());
+    drop(fm);
+    drop(storage);
+
+    let fm = FileMgr::new(dir.to_str().unwrap(), 400).unwrap();
+    assert_eq!(1, read_int(&fm, &logblk).unwrap());
+    assert_eq!(0, fm.length("datafile").unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_stale_extent_entry() {
+    let dir = test_dir("stale_extent_entry");
+    let open = |storage: Box<dyn Storage>| {
+        let options = FileMgrOptions {
+            compression: true,
+            ..Default::default()
+        };
+        FileMgr::new_from_storage(storage, 400, options).unwrap()
+    };
+    let inner = FileStorage::new(dir.to_str().unwrap()).unwrap();
+    let storage = Arc::new(FaultStorage::new_with_volatile_writes(Box::new(inner)));
+    let fm = open(Box::new(Arc::clone(&storage)));
+
+    // a marker followed by n bytes that don't compress
+    let write_noise = |blk: &BlockId, marker: i32, n: usize| {
+        let mut seed = marker as u32;
+        let noise: Vec<u8> = (0..n)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        let mut p = Page::new_from_size(fm.blocksize() as usize);
+        p.set_int(0, marker).unwrap();
+        p.set_bytes(4, &noise).unwrap();
+        fm.write(blk, &mut p).unwrap();
+    };
+    let blk0 = BlockId::new("packedfile", 0);
+    let blk1 = BlockId::new("packedfile", 1);
+
+    write_noise(&blk0, 7, 150);
+    fm.append("packedfile").unwrap();
+    fm.sync("packedfile").unwrap();
+
+    // block 0 grows out of its extent, and block 1 takes the sectors it left behind.
+    // Only the data file reaches the disk, the sidecar still points block 0 at them
+    write_noise(&blk0, 8, 390);
+    write_noise(&blk1, 9, 120);
+    storage.sync("packedfile").unwrap();
+    storage.inject(Fault::CrashAfterWrites(storage.writes()));
+    assert!(write_int(&fm, &blk1, 10).is_err());
+    drop(fm);
+    drop(storage);
+
+    let fm = open(Box::new(FileStorage::new(dir.to_str().unwrap()).unwrap()));
+    let err = read_int(&fm, &blk0).unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::Corrupted(blk0.clone())),
+        err.downcast_ref::<FileMgrError>()
+    );
+    // nor did the entry of block 1
+    assert_eq!(0, read_int(&fm, &blk1).unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,11 +1,37 @@
 use simple_db::blockid::BlockId;
-use simple_db::filemanager::FileMgr;
+use simple_db::buffermanager::BufferMgr;
+use simple_db::filemanager::{Durability, FileMgr, FileMgrError, FileMgrOptions};
+use simple_db::filestorage::{IoMode, LOCK_FILE};
+use simple_db::logmanager::{LogMgr, SHADOW_SUFFIX};
+use simple_db::memorystorage::MemoryStorage;
 use simple_db::page::Page;
+use simple_db::storage::Storage;
+use simple_db::superblock::SUPERBLOCK_FILE;
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// a fresh directory for each test, so that tests don't see each other's files
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("simpledb-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    dir
+}
 
 #[test]
 fn test_new_filemgr() {
-    let mut fm = FileMgr::new("./testdb", 400).unwrap();
-    let mut blk = BlockId::new("testfile", 2);
+    let dir = test_dir("new_filemgr");
+    let fm = FileMgr::new(dir.to_str().unwrap(), 400).unwrap();
+    let blk = BlockId::new("testfile", 2);
 
     let mut p1 = Page::new_from_size(fm.blocksize() as usize);
     let pos1 = 88;
@@ -16,10 +42,10 @@ fn test_new_filemgr() {
 
     // println!("{}", p1.contents_str());
 
-    fm.write(&mut blk, &mut p1).unwrap();
+    fm.write(&blk, &mut p1).unwrap();
 
     let mut p2 = Page::new_from_size(fm.blocksize() as usize);
-    fm.read(&mut blk, &mut p2).unwrap();
+    fm.read(&blk, &mut p2).unwrap();
 
     // string
     assert_eq!(String::from("abcdefghijklm"), p2.get_string(pos1).unwrap());
@@ -32,7 +58,815 @@ fn test_new_filemgr() {
     // int
     assert_eq!(345, p2.get_int(pos2).unwrap());
     println!("offset: {}, contains: {}", pos2, p2.get_int(pos2).unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_mutex() {
+    let dir = test_dir("mutex");
+    let fm = Arc::new(FileMgr::new(dir.to_str().unwrap(), 400).unwrap());
+    let nthreads = 8;
+
+    let handles: Vec<_> = (0..nthreads)
+        .map(|i| {
+            let fm = Arc::clone(&fm);
+            thread::spawn(move || {
+                let blk = BlockId::new("mutexfile", i);
+                let mut p = Page::new_from_size(fm.blocksize() as usize);
+                for n in 0..50 {
+                    p.set_int(0, (i * 1000 + n) as i32).unwrap();
+                    fm.write(&blk, &mut p).unwrap();
+                    fm.read(&blk, &mut p).unwrap();
+                    assert_eq!((i * 1000 + n) as i32, p.get_int(0).unwrap());
+                }
+            })
+        })
+        .collect();
+
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    for i in 0..nthreads {
+        let mut p = Page::new_from_size(fm.blocksize() as usize);
+        fm.read(&BlockId::new("mutexfile", i), &mut p).unwrap();
+        assert_eq!((i * 1000 + 49) as i32, p.get_int(0).unwrap());
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_checksum() {
+    let dir = test_dir("checksum");
+    let fm = FileMgr::new(dir.to_str().unwrap(), 400).unwrap();
+    let blk = BlockId::new("checksumfile", 1);
+
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    p.set_string(0, "checksummed").unwrap();
+    fm.write(&blk, &mut p).unwrap();
+
+    // block 0 is a hole that was never appended, its zeroes have no checksum
+    // and read as damage, just like a block that was zeroed on disk
+    let blk0 = BlockId::new("checksumfile", 0);
+    let mut p0 = Page::new_from_size(fm.blocksize() as usize);
+    let err = fm.read(&blk0, &mut p0).unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::Corrupted(blk0)),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    // an appended block is all zeroes, stored with their checksum
+    let appended = fm.append("checksumfile").unwrap();
+    assert_eq!(2, appended.number());
+    fm.read(&appended, &mut p0).unwrap();
+    assert!(p0.contents().iter().all(|b| *b == 0));
+    fm.truncate("checksumfile", 2).unwrap();
+
+    // flip a byte of block 1 behind the manager's back
+    let mut f = OpenOptions::new()
+        .write(true)
+        .open(dir.join("checksumfile"))
+        .unwrap();
+    let len = f.metadata().unwrap().len();
+    f.seek(SeekFrom::Start(len / 2 + 10)).unwrap();
+    f.write_all(&[0xff]).unwrap();
+    f.sync_all().unwrap();
+
+    let err = fm.read(&blk, &mut p).unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::Corrupted(blk.clone())),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    // a torn write leaves only part of the block on disk
+    f.set_len(len - 100).unwrap();
+    let err = fm.read(&blk, &mut p).unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::Corrupted(blk)),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_durability() {
+    let dir = test_dir("durability");
+    let fm = FileMgr::new(dir.to_str().unwrap(), 400).unwrap();
+    assert_eq!(Durability::LogFlush, fm.durability());
+    drop(fm);
+
+    let options = FileMgrOptions {
+        durability: Durability::EveryWrite,
+        ..Default::default()
+    };
+    let fm = FileMgr::new_with_options(dir.to_str().unwrap(), 400, options).unwrap();
+    assert_eq!(Durability::EveryWrite, fm.durability());
+
+    let blk = fm.append("durablefile").unwrap();
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    p.set_int(0, 42).unwrap();
+    fm.write(&blk, &mut p).unwrap();
+    fm.sync("durablefile").unwrap();
+
+    let mut p2 = Page::new_from_size(fm.blocksize() as usize);
+    fm.read(&blk, &mut p2).unwrap();
+    assert_eq!(42, p2.get_int(0).unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+
+    // what each policy syncs: log flushes, buffer flushes, or nothing
+    let fsyncs = |durability: Durability| {
+        let storage = Arc::new(CountSyncs::new());
+        let options = FileMgrOptions {
+            durability,
+            ..Default::default()
+        };
+        let fm = Arc::new(
+            FileMgr::new_from_storage(Box::new(Arc::clone(&storage)), 400, options).unwrap(),
+        );
+        let lm = Arc::new(LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap());
+        let mut bm = BufferMgr::new(Arc::clone(&fm), Arc::clone(&lm), 3);
+        let blk = fm.append("datafile").unwrap();
+        storage.reset();
+
+        let lsn = lm.append(b"commit").unwrap();
+        lm.flush_from_lsn(lsn).unwrap();
+        // the log block and its copy, a flush writes one of them
+        let log_syncs =
+            storage.syncs("logfile") + storage.syncs(&format!("logfile{}", SHADOW_SUFFIX));
+
+        let buff = bm.pin(&blk).unwrap();
+        buff.borrow_mut().contents().set_int(0, 42).unwrap();
+        buff.borrow_mut()
+            .set_modified(1, lm.append(b"update").unwrap() as i64);
+        buff.borrow_mut().flush().unwrap();
+
+        (log_syncs, storage.syncs("datafile"))
+    };
+    let (log_syncs, data_syncs) = fsyncs(Durability::LogFlush);
+    assert!(log_syncs > 0);
+    assert_eq!(0, data_syncs);
+    let (_, data_syncs) = fsyncs(Durability::EveryWrite);
+    assert!(data_syncs > 0);
+    assert_eq!((0, 0), fsyncs(Durability::None));
+}
+
+// counts the syncs of each file
+struct CountSyncs {
+    inner: MemoryStorage,
+    syncs: Mutex<HashMap<String, usize>>,
+}
+
+impl CountSyncs {
+    fn new() -> CountSyncs {
+        CountSyncs {
+            inner: MemoryStorage::new(),
+            syncs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn syncs(&self, filename: &str) -> usize {
+        *self.syncs.lock().unwrap().get(filename).unwrap_or(&0)
+    }
+
+    fn reset(&self) {
+        self.syncs.lock().unwrap().clear();
+    }
+}
+
+impl Storage for CountSyncs {
+    fn read_block(&self, filename: &str, blknum: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_block(filename, blknum, buf)
+    }
+
+    fn write_block(&self, filename: &str, blknum: u64, buf: &[u8]) -> Result<()> {
+        self.inner.write_block(filename, blknum, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_at(filename, offset, buf)
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<()> {
+        self.inner.write_at(filename, offset, buf)
+    }
+
+    fn append_block(&self, filename: &str, buf: &[u8]) -> Result<u64> {
+        self.inner.append_block(filename, buf)
+    }
+
+    fn length(&self, filename: &str, blocksize: u64) -> Result<u64> {
+        self.inner.length(filename, blocksize)
+    }
+
+    fn truncate(&self, filename: &str, nblocks: u64, blocksize: u64) -> Result<()> {
+        self.inner.truncate(filename, nblocks, blocksize)
+    }
+
+    fn delete(&self, filename: &str) -> Result<()> {
+        self.inner.delete(filename)
+    }
+
+    fn sync(&self, filename: &str) -> Result<()> {
+        *self
+            .syncs
+            .lock()
+            .unwrap()
+            .entry(filename.into())
+            .or_default() += 1;
+        self.inner.sync(filename)
+    }
+
+    fn filenames(&self) -> Result<Vec<String>> {
+        self.inner.filenames()
+    }
+}
+
+#[test]
+fn test_directory_lock() {
+    let dir = test_dir("directory_lock");
+    let dirname = dir.to_str().unwrap();
+    let shared = || FileMgrOptions {
+        read_only: true,
+        ..Default::default()
+    };
+
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    let err = FileMgr::new(dirname, 400).err().unwrap();
+    assert_eq!(
+        Some(&FileMgrError::DatabaseInUse(dirname.into())),
+        err.downcast_ref::<FileMgrError>()
+    );
+    assert!(FileMgr::new_with_options(dirname, 400, shared()).is_err());
+    drop(fm);
+
+    // readers share the directory, but keep writers out
+    let reader1 = FileMgr::new_with_options(dirname, 400, shared()).unwrap();
+    let reader2 = FileMgr::new_with_options(dirname, 400, shared()).unwrap();
+    assert!(FileMgr::new(dirname, 400).is_err());
+    drop(reader1);
+    drop(reader2);
+
+    assert!(FileMgr::new(dirname, 400).is_ok());
+
+    // a reader can't hold a directory without a lock file against a later writer
+    fs::remove_file(dir.join(LOCK_FILE)).unwrap();
+    assert!(FileMgr::new_with_options(dirname, 400, shared()).is_err());
+    assert!(!dir.join(LOCK_FILE).exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_superblock() {
+    let dir = test_dir("superblock");
+    let dirname = dir.to_str().unwrap();
+
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    assert!(fm.is_new());
+    drop(fm);
+
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    assert!(!fm.is_new());
+    drop(fm);
+
+    let err = FileMgr::new(dirname, 800).err().unwrap();
+    assert_eq!(
+        Some(&FileMgrError::BlockSizeMismatch {
+            stored: 400,
+            requested: 800
+        }),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    // a damaged superblock is refused
+    let mut f = OpenOptions::new()
+        .write(true)
+        .open(dir.join(SUPERBLOCK_FILE))
+        .unwrap();
+    f.write_all(&[0]).unwrap();
+    f.sync_all().unwrap();
+    let err = FileMgr::new(dirname, 400).err().unwrap();
+    assert_eq!(
+        Some(&FileMgrError::NotADatabase),
+        err.downcast_ref::<FileMgrError>()
+    );
+    fs::remove_dir_all(&dir).unwrap();
+
+    // a directory of other files is left as it was, temporary-looking names included
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("template.docx"), b"letter").unwrap();
+    fs::write(dir.join("notes.txt"), b"todo").unwrap();
+    let err = FileMgr::new(dirname, 400).err().unwrap();
+    assert_eq!(
+        Some(&FileMgrError::NotADatabase),
+        err.downcast_ref::<FileMgrError>()
+    );
+    let mut names: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(vec!["notes.txt", "template.docx"], names);
+
+    // the same through any storage
+    let storage = Arc::new(MemoryStorage::new());
+    storage.write_at("template.docx", 0, b"letter").unwrap();
+    assert!(FileMgr::new_from_storage(
+        Box::new(Arc::clone(&storage)),
+        400,
+        FileMgrOptions::default()
+    )
+    .is_err());
+    assert_eq!(vec!["template.docx"], storage.filenames().unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_free_truncate_delete() {
+    let dir = test_dir("free_truncate_delete");
+    let dirname = dir.to_str().unwrap();
+
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    for i in 0..4 {
+        let blk = fm.append("freefile").unwrap();
+        p.set_int(0, 100 + i).unwrap();
+        fm.write(&blk, &mut p).unwrap();
+    }
+    fm.free(&BlockId::new("freefile", 2)).unwrap();
+    fm.free(&BlockId::new("freefile", 1)).unwrap();
+
+    let err = fm.free(&BlockId::new("freefile", 4)).unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::BlockOutOfRange(BlockId::new("freefile", 4))),
+        err.downcast_ref::<FileMgrError>()
+    );
+    let err = fm.delete(SUPERBLOCK_FILE).unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::ReservedFile(SUPERBLOCK_FILE.into())),
+        err.downcast_ref::<FileMgrError>()
+    );
+    drop(fm);
+
+    // the free list survives a reopen, freed blocks come back zeroed
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    let blk = fm.append("freefile").unwrap();
+    assert_eq!(1, blk.number());
+    fm.read(&blk, &mut p).unwrap();
+    assert_eq!(0, p.get_int(0).unwrap());
+    assert_eq!(2, fm.append("freefile").unwrap().number());
+    assert_eq!(4, fm.append("freefile").unwrap().number());
+    assert_eq!(5, fm.length("freefile").unwrap());
+
+    // freed blocks past the new end are forgotten
+    fm.free(&BlockId::new("freefile", 3)).unwrap();
+    fm.truncate("freefile", 2).unwrap();
+    assert_eq!(2, fm.length("freefile").unwrap());
+    assert_eq!(2, fm.append("freefile").unwrap().number());
+
+    fm.free(&BlockId::new("freefile", 0)).unwrap();
+    fm.delete("freefile").unwrap();
+    assert_eq!(0, fm.length("freefile").unwrap());
+    assert_eq!(0, fm.append("freefile").unwrap().number());
+    assert_eq!(1, fm.append("freefile").unwrap().number());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_large_freelist() {
+    let dir = test_dir("large_freelist");
+    let dirname = dir.to_str().unwrap();
+
+    // more free blocks than fit in one block of the free list
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    for _ in 0..200 {
+        fm.append("bigfile").unwrap();
+    }
+    for i in (0..200).step_by(2) {
+        fm.free(&BlockId::new("bigfile", i)).unwrap();
+    }
+    drop(fm);
+
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    for i in (0..200).step_by(2) {
+        assert_eq!(i, fm.append("bigfile").unwrap().number());
+    }
+    assert_eq!(200, fm.append("bigfile").unwrap().number());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_freelist_synced() {
+    // even where nothing else is synced, a free list lost in a crash would leak its blocks
+    for durability in [Durability::None, Durability::LogFlush] {
+        let storage = Arc::new(CountSyncs::new());
+        let options = FileMgrOptions {
+            durability,
+            ..Default::default()
+        };
+        let fm = FileMgr::new_from_storage(Box::new(Arc::clone(&storage)), 400, options).unwrap();
+        for _ in 0..3 {
+            fm.append("datafile").unwrap();
+        }
+        storage.reset();
+
+        fm.free(&BlockId::new("datafile", 1)).unwrap();
+        assert_eq!(1, storage.syncs("freelist"));
+        fm.append("datafile").unwrap();
+        assert_eq!(2, storage.syncs("freelist"));
+        fm.truncate("datafile", 1).unwrap();
+        assert_eq!(3, storage.syncs("freelist"));
+        assert_eq!(0, storage.syncs("datafile"));
+    }
+}
+
+#[test]
+fn test_handle_cache() {
+    let dir = test_dir("handle_cache");
+    let options = FileMgrOptions {
+        max_open_files: 2,
+        ..Default::default()
+    };
+    let fm = FileMgr::new_with_options(dir.to_str().unwrap(), 400, options).unwrap();
+    let before = fm.handle_cache_stats().unwrap();
+
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    for round in 0..3 {
+        for i in 0..3 {
+            let blk = BlockId::new(format!("cachefile{}", i), 0);
+            if round == 0 {
+                p.set_int(0, i).unwrap();
+                fm.write(&blk, &mut p).unwrap();
+            }
+            // reopened transparently after being evicted
+            fm.read(&blk, &mut p).unwrap();
+            assert_eq!(i, p.get_int(0).unwrap());
+        }
+    }
+
+    let stats = fm.handle_cache_stats().unwrap();
+    assert!(stats.open <= 2);
+    assert!(stats.evictions - before.evictions >= 7);
+    // with 3 files and room for 2, only the read right after a write hits
+    assert!(stats.hits - before.hits >= 3);
+    assert!(stats.misses > before.misses);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_mmap() {
+    let dir = test_dir("mmap");
+    let dirname = dir.to_str().unwrap();
+    let mmap = || FileMgrOptions {
+        io_mode: IoMode::Mmap,
+        ..Default::default()
+    };
+
+    let fm = Arc::new(FileMgr::new_with_options(dirname, 400, mmap()).unwrap());
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let fm = Arc::clone(&fm);
+            thread::spawn(move || {
+                let mut p = Page::new_from_size(fm.blocksize() as usize);
+                for n in 0..10 {
+                    // the mapping grows with every append
+                    let blk = fm.append(format!("mmapfile{}", i)).unwrap();
+                    p.set_int(0, i * 100 + n).unwrap();
+                    fm.write(&blk, &mut p).unwrap();
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    fm.read(&BlockId::new("mmapfile3", 9), &mut p).unwrap();
+    assert_eq!(309, p.get_int(0).unwrap());
+
+    fm.truncate("mmapfile3", 5).unwrap();
+    assert_eq!(5, fm.length("mmapfile3").unwrap());
+    fm.read(&BlockId::new("mmapfile3", 9), &mut p).unwrap();
+    assert_eq!(0, p.get_int(0).unwrap());
+    fm.sync("mmapfile3").unwrap();
+    drop(fm);
+
+    // what was written through the mapping is in the file
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    for i in 0..4 {
+        fm.read(&BlockId::new(format!("mmapfile{}", i), 4), &mut p)
+            .unwrap();
+        assert_eq!(i * 100 + 4, p.get_int(0).unwrap());
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_mmap_eviction() {
+    let dir = test_dir("mmap_eviction");
+    let options = FileMgrOptions {
+        io_mode: IoMode::Mmap,
+        max_open_files: 1,
+        ..Default::default()
+    };
+    let fm = Arc::new(FileMgr::new_with_options(dir.to_str().unwrap(), 1 << 16, options).unwrap());
+
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    for _ in 0..4 {
+        let blk = fm.append("shrinkfile").unwrap();
+        fm.write(&blk, &mut p).unwrap();
+    }
+
+    // a reader may still use the mapping of an evicted handle while the file is
+    // truncated through a new one, which must not pull the pages out from under it
+    let done = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let fm = Arc::clone(&fm);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut p = Page::new_from_size(fm.blocksize() as usize);
+                while !done.load(Ordering::Relaxed) {
+                    fm.read(&BlockId::new("shrinkfile", 3), &mut p).unwrap();
+                }
+            })
+        })
+        .collect();
+    for _ in 0..200 {
+        fm.read(&BlockId::new("otherfile", 0), &mut p).unwrap();
+        fm.truncate("shrinkfile", 1).unwrap();
+        fm.read(&BlockId::new("otherfile", 0), &mut p).unwrap();
+        while fm.length("shrinkfile").unwrap() < 4 {
+            let blk = fm.append("shrinkfile").unwrap();
+            fm.write(&blk, &mut p).unwrap();
+        }
+    }
+    done.store(true, Ordering::Relaxed);
+    for r in readers {
+        r.join().unwrap();
+    }
+    assert!(fm.handle_cache_stats().unwrap().open <= 1);
+
+    fs::remove_dir_all(dir).unwrap();
 }
 
 #[test]
-fn test_mutex() {}
+#[cfg(target_os = "linux")]
+fn test_direct_io() {
+    let dir = test_dir("direct_io");
+    let dirname = dir.to_str().unwrap();
+    let direct = || FileMgrOptions {
+        io_mode: IoMode::Direct,
+        ..Default::default()
+    };
+
+    let err = FileMgr::new_with_options(dirname, 400, direct())
+        .err()
+        .unwrap();
+    assert_eq!(
+        Some(&FileMgrError::InvalidBlockSize {
+            blocksize: 400,
+            alignment: 4096
+        }),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    // a block and its checksum fill exactly one aligned unit
+    let blocksize = 4096 - 4;
+    let fm = FileMgr::new_with_options(dirname, blocksize, direct()).unwrap();
+    // any page will do, FileMgr aligns the slots it reads and writes itself
+    let mut p = Page::new_from_size(blocksize as usize);
+
+    for i in 0..3 {
+        let blk = fm.append("directfile").unwrap();
+        p.set_int(0, i).unwrap();
+        p.set_string(blocksize as usize - 20, "end of block")
+            .unwrap();
+        fm.write(&blk, &mut p).unwrap();
+    }
+    fm.read(&BlockId::new("directfile", 1), &mut p).unwrap();
+    assert_eq!(1, p.get_int(0).unwrap());
+    drop(fm);
+
+    let fm = FileMgr::new(dirname, blocksize).unwrap();
+    let mut p = Page::new_from_size(blocksize as usize);
+    fm.read(&BlockId::new("directfile", 2), &mut p).unwrap();
+    assert_eq!(2, p.get_int(0).unwrap());
+    assert_eq!(
+        "end of block",
+        p.get_string(blocksize as usize - 20).unwrap()
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_compression() {
+    let dir = test_dir("compression");
+    let dirname = dir.to_str().unwrap();
+    let compressed = || FileMgrOptions {
+        compression: true,
+        ..Default::default()
+    };
+
+    // compressed extents are not aligned, direct I/O could never be used for them
+    let options = FileMgrOptions {
+        io_mode: IoMode::Direct,
+        ..compressed()
+    };
+    let err = FileMgr::new_from_storage(Box::new(MemoryStorage::new()), 4092, options)
+        .err()
+        .unwrap();
+    assert_eq!(
+        Some(&FileMgrError::IncompatibleOptions(
+            "compression with direct I/O".into()
+        )),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    let fm = FileMgr::new_with_options(dirname, 400, compressed()).unwrap();
+    assert!(fm.is_compressed());
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    for i in 0..20 {
+        let blk = fm.append("strings").unwrap();
+        for slot in 0..4 {
+            p.set_string(slot * 100, format!("repetitive value {}", i % 3))
+                .unwrap();
+        }
+        fm.write(&blk, &mut p).unwrap();
+    }
+
+    // repetitive blocks take a fraction of their size on disk
+    let len = fs::metadata(dir.join("strings")).unwrap().len();
+    assert!(len < 20 * 400 / 4, "{} bytes on disk", len);
+
+    // a block that no longer compresses moves to a larger extent
+    let noise: Vec<u8> = (0..300u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    p.set_bytes(50, &noise).unwrap();
+    fm.write(&BlockId::new("strings", 7), &mut p).unwrap();
+
+    fm.truncate("strings", 10).unwrap();
+    assert_eq!(10, fm.length("strings").unwrap());
+    drop(fm);
+
+    // the extents survive a reopen
+    let fm = FileMgr::new_with_options(dirname, 400, compressed()).unwrap();
+    fm.read(&BlockId::new("strings", 7), &mut p).unwrap();
+    assert_eq!(noise, p.get_bytes(50).unwrap());
+    fm.read(&BlockId::new("strings", 8), &mut p).unwrap();
+    assert_eq!("repetitive value 2", p.get_str(300).unwrap());
+    fm.read(&BlockId::new("strings", 12), &mut p).unwrap();
+    assert!(p.contents().iter().all(|b| *b == 0));
+
+    // a stored block that was damaged is reported
+    let mut f = OpenOptions::new()
+        .write(true)
+        .open(dir.join("strings"))
+        .unwrap();
+    f.write_all(&[0xff; 16]).unwrap();
+    f.sync_all().unwrap();
+    let corrupted: Vec<_> = (0..10)
+        .filter_map(|n| fm.read(&BlockId::new("strings", n), &mut p).err())
+        .collect();
+    assert_eq!(1, corrupted.len());
+    assert!(matches!(
+        corrupted[0].downcast_ref::<FileMgrError>(),
+        Some(FileMgrError::Corrupted(_))
+    ));
+    drop(fm);
+
+    // the database can only be opened with compression
+    let err = FileMgr::new(dirname, 400).err().unwrap();
+    assert_eq!(
+        Some(&FileMgrError::FeatureMismatch {
+            feature: "compression".into(),
+            stored: true
+        }),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_io_stats() {
+    let dir = test_dir("io_stats");
+    let fm = FileMgr::new(dir.to_str().unwrap(), 400).unwrap();
+    fm.reset_io_stats();
+
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    for i in 0..3 {
+        let blk = fm.append("statsfile").unwrap();
+        p.set_int(0, i).unwrap();
+        fm.write(&blk, &mut p).unwrap();
+    }
+    for i in 0..3 {
+        fm.read(&BlockId::new("statsfile", i), &mut p).unwrap();
+    }
+    fm.read(&BlockId::new("otherfile", 0), &mut p).unwrap();
+
+    // every block moves with its 4 byte checksum
+    let stats = fm.io_stats();
+    let file = stats.file("statsfile");
+    assert_eq!((3, 3, 3), (file.appends, file.writes, file.reads));
+    assert_eq!(6 * 404, file.bytes_written);
+    assert_eq!(3 * 404, file.bytes_read);
+    assert_eq!(4, stats.total.reads);
+    assert_eq!(1, stats.file("otherfile").reads);
+    assert_eq!(0, stats.file("otherfile").bytes_read);
+    assert_eq!(0, stats.file("nofile").reads);
+
+    fm.reset_io_stats();
+    assert_eq!(0, fm.io_stats().total.reads);
+    assert!(fm.io_stats().files.is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_read_only() {
+    let dir = test_dir("read_only");
+    let dirname = dir.to_str().unwrap();
+    let read_only = |io_mode| FileMgrOptions {
+        read_only: true,
+        io_mode,
+        ..Default::default()
+    };
+
+    // there is nothing to open yet
+    assert!(FileMgr::new_with_options(dirname, 400, read_only(IoMode::Standard)).is_err());
+    assert!(!dir.exists());
+
+    let fm = FileMgr::new(dirname, 400).unwrap();
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    for i in 0..3 {
+        let blk = fm.append("rofile").unwrap();
+        p.set_int(0, 10 + i).unwrap();
+        fm.write(&blk, &mut p).unwrap();
+    }
+    fm.free(&BlockId::new("rofile", 1)).unwrap();
+    drop(fm);
+    // as if a crash left it behind
+    fs::write(dir.join("temp7"), b"scratch").unwrap();
+
+    let listing = || {
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        names
+    };
+    let before = listing();
+
+    for io_mode in [IoMode::Standard, IoMode::Mmap] {
+        let fm = FileMgr::new_with_options(dirname, 400, read_only(io_mode)).unwrap();
+        assert!(fm.is_read_only());
+        fm.read(&BlockId::new("rofile", 2), &mut p).unwrap();
+        assert_eq!(12, p.get_int(0).unwrap());
+
+        // a missing file reads as empty and is not created
+        assert_eq!(0, fm.length("nofile").unwrap());
+        fm.read(&BlockId::new("nofile", 0), &mut p).unwrap();
+        assert_eq!(0, p.get_int(0).unwrap());
+
+        let is_read_only = |r: anyhow::Result<()>| {
+            matches!(
+                r.unwrap_err().downcast_ref::<FileMgrError>(),
+                Some(FileMgrError::ReadOnly(_))
+            )
+        };
+        assert!(is_read_only(fm.write(&BlockId::new("rofile", 0), &mut p)));
+        assert!(is_read_only(fm.append("rofile").map(|_| ())));
+        assert!(is_read_only(fm.free(&BlockId::new("rofile", 0))));
+        assert!(is_read_only(fm.truncate("rofile", 1)));
+        assert!(is_read_only(fm.delete("rofile")));
+        assert!(is_read_only(fm.new_temp_filename().map(|_| ())));
+        fm.sync("rofile").unwrap();
+
+        // readers share the directory, a writer has to wait for them
+        let reader = FileMgr::new_with_options(dirname, 400, read_only(io_mode)).unwrap();
+        let err = FileMgr::new(dirname, 400).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<FileMgrError>(),
+            Some(FileMgrError::DatabaseInUse(_))
+        ));
+        drop(reader);
+    }
+
+    assert_eq!(before, listing());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,22 +1,445 @@
-use simple_db::filemanager::FileMgr;
-use simple_db::logmanager::LogMgr;
+use simple_db::blockid::BlockId;
+use simple_db::faultstorage::{Fault, FaultStorage};
+use simple_db::filemanager::{FileMgr, FileMgrError, FileMgrOptions};
+use simple_db::logmanager::{LogMgr, LogMgrError, SHADOW_SUFFIX};
+use simple_db::memorystorage::MemoryStorage;
 use simple_db::page::Page;
+use simple_db::storage::Storage;
 
-use std::cell::RefCell;
 use std::mem;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use anyhow::Result;
 
+fn new_logmgr() -> LogMgr {
+    let fm = FileMgr::new_from_storage(
+        Box::new(MemoryStorage::new()),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+
+    LogMgr::new(Arc::new(fm), String::from("logfile")).unwrap()
+}
+
 #[test]
 fn log_test() {
-    let fm = FileMgr::new("./logtests", 400).unwrap();
-    let mut lm = LogMgr::new(Arc::new(RefCell::new(fm)), String::from("logfile")).unwrap();
-    create_records(&mut lm, 1, 35).unwrap();
-    print_log_record(&mut lm, String::from("The log file now has these records:")).unwrap();
+    let lm = new_logmgr();
+    create_records(&lm, 1, 35).unwrap();
+    print_log_record(&lm, String::from("The log file now has these records:")).unwrap();
+}
+
+#[test]
+fn test_next_record() {
+    let lm = new_logmgr();
+    create_records(&lm, 1, 35).unwrap();
+
+    let mut iter = lm.iterator().unwrap();
+    let mut i = 35;
+    while let Some(rec) = iter.next_record() {
+        // decode straight from the iterator's page
+        let len = i32::from_be_bytes([rec[0], rec[1], rec[2], rec[3]]) as usize;
+        let s = std::str::from_utf8(&rec[4..4 + len]).unwrap();
+        assert_eq!(format!("record{}", i), s);
+        i -= 1;
+    }
+    assert_eq!(0, i);
+}
+
+#[test]
+fn test_read_only_log() {
+    let storage = Arc::new(MemoryStorage::new());
+    let open = |read_only| {
+        let options = FileMgrOptions {
+            read_only,
+            ..Default::default()
+        };
+        Arc::new(FileMgr::new_from_storage(Box::new(Arc::clone(&storage)), 400, options).unwrap())
+    };
+
+    let lm = LogMgr::new(open(false), String::from("logfile")).unwrap();
+    create_records(&lm, 1, 5).unwrap();
+    lm.iterator().unwrap();
+    drop(lm);
+
+    let lm = LogMgr::new(open(true), String::from("logfile")).unwrap();
+    assert_eq!(5, lm.iterator().unwrap().count());
+    let err = lm.append(b"record6").unwrap_err();
+    assert_eq!(
+        Some(&FileMgrError::ReadOnly(String::from("logfile"))),
+        err.downcast_ref::<FileMgrError>()
+    );
+
+    // an empty log is not created
+    let lm = LogMgr::new(open(true), String::from("emptylog")).unwrap();
+    assert_eq!(0, lm.iterator().unwrap().count());
+    assert!(!storage
+        .filenames()
+        .unwrap()
+        .contains(&String::from("emptylog")));
+}
+
+#[test]
+fn test_lsn() {
+    let storage = Arc::new(MemoryStorage::new());
+    let open = || {
+        let fm = FileMgr::new_from_storage(
+            Box::new(Arc::clone(&storage)),
+            400,
+            FileMgrOptions::default(),
+        )
+        .unwrap();
+        LogMgr::new(Arc::new(fm), String::from("logfile")).unwrap()
+    };
+
+    let lm = open();
+    assert_eq!(0, lm.latest_lsn().unwrap());
+    // every record gets the next LSN, across blocks
+    for expected in 1..=35 {
+        let mut rec = create_log_record(format!("record{}", expected), expected).unwrap();
+        assert_eq!(expected, lm.append(rec.contents()).unwrap());
+    }
+    assert!(lm.lastsaved_lsn().unwrap() < 35);
+    lm.flush_from_lsn(35).unwrap();
+    assert_eq!(35, lm.lastsaved_lsn().unwrap());
+
+    // records that never reached the disk are gone after a restart, their LSNs are handed out again
+    lm.append(b"lost").unwrap();
+    drop(lm);
+
+    let lm = open();
+    assert_eq!(35, lm.latest_lsn().unwrap());
+    assert_eq!(35, lm.lastsaved_lsn().unwrap());
+    assert_eq!(36, lm.append(b"record36").unwrap());
+    assert_eq!(36, lm.iterator().unwrap().count());
+}
+
+#[test]
+fn test_forward_iterator() {
+    let fm = Arc::new(
+        FileMgr::new_from_storage(
+            Box::new(MemoryStorage::new()),
+            400,
+            FileMgrOptions::default(),
+        )
+        .unwrap(),
+    );
+    let lm = LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap();
+    create_records(&lm, 1, 35).unwrap();
+    let record = |i: u64| {
+        create_log_record(format!("record{}", i), i + 100)
+            .unwrap()
+            .contents()
+            .to_vec()
+    };
+
+    // oldest first, across blocks
+    let recs: Vec<(u64, Vec<u8>)> = lm.forward_iterator(1).unwrap().collect();
+    assert_eq!(35, recs.len());
+    for (i, (lsn, rec)) in recs.into_iter().enumerate() {
+        assert_eq!(i as u64 + 1, lsn);
+        assert_eq!(record(lsn), rec);
+    }
+
+    let lsns: Vec<u64> = lm
+        .forward_iterator(20)
+        .unwrap()
+        .map(|(lsn, _)| lsn)
+        .collect();
+    assert_eq!((20..=35).collect::<Vec<u64>>(), lsns);
+    assert_eq!(0, lm.forward_iterator(36).unwrap().count());
+
+    // a block starts where the one before it ends
+    let mut iter = lm.forward_iterator_from_block(1).unwrap();
+    let (first, _) = iter.next_record().unwrap();
+    assert!(first > 1);
+    assert_eq!(35, first + iter.count() as u64);
+    assert_eq!(35, lm.forward_iterator_from_block(0).unwrap().count());
+
+    // lookups in a log that is on disk already write nothing
+    let writes = fm.io_stats().file("logfile").writes;
+    for _ in 0..100 {
+        assert_eq!(Some(record(17)), lm.record(17).unwrap());
+    }
+    assert_eq!(writes, fm.io_stats().file("logfile").writes);
+
+    assert_eq!(Some(record(35)), lm.record(35).unwrap());
+    assert_eq!(None, lm.record(0).unwrap());
+    assert_eq!(None, lm.record(36).unwrap());
+}
+
+#[test]
+fn test_large_records() {
+    let storage = Arc::new(MemoryStorage::new());
+    let fm = Arc::new(
+        FileMgr::new_from_storage(
+            Box::new(Arc::clone(&storage)),
+            400,
+            FileMgrOptions::default(),
+        )
+        .unwrap(),
+    );
+    let open = || LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap();
+    let big = |n: usize| (0..n).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+
+    let lm = open();
+    assert_eq!(1, lm.append(b"small1").unwrap());
+    // several blocks long
+    assert_eq!(2, lm.append(&big(1000)).unwrap());
+    assert_eq!(3, lm.append(b"small3").unwrap());
+    assert_eq!(4, lm.append(&big(390)).unwrap());
+    let expected = vec![b"small1".to_vec(), big(1000), b"small3".to_vec(), big(390)];
+
+    let mut backward: Vec<Vec<u8>> = lm.iterator().unwrap().collect();
+    backward.reverse();
+    assert_eq!(expected, backward);
+    let mut iter = lm.iterator().unwrap();
+    assert_eq!(Some(&big(390)[..]), iter.next_record());
+
+    let forward: Vec<(u64, Vec<u8>)> = lm.forward_iterator(1).unwrap().collect();
+    assert_eq!(
+        (1..=4).zip(expected.iter().cloned()).collect::<Vec<_>>(),
+        forward
+    );
+    assert_eq!(Some(big(1000)), lm.record(2).unwrap());
+    let mut iter = lm.forward_iterator(3).unwrap();
+    assert_eq!(Some((3, &b"small3"[..])), iter.next_record());
+    assert_eq!(Some((4, &big(390)[..])), iter.next_record());
+    drop(lm);
+
+    // the log ends in the middle of a record, its blocks before the last one are on disk already
+    let lm = open();
+    let nblocks = fm.length("logfile").unwrap();
+    assert_eq!(5, lm.append(&big(2000)).unwrap());
+    assert!(fm.length("logfile").unwrap() > nblocks + 3);
+    drop(lm);
+
+    let lm = open();
+    assert_eq!(4, lm.latest_lsn().unwrap());
+    assert_eq!(nblocks, fm.length("logfile").unwrap());
+    assert_eq!(expected.len(), lm.iterator().unwrap().count());
+    assert_eq!(5, lm.append(b"small5").unwrap());
+    assert_eq!(Some(b"small5".to_vec()), lm.record(5).unwrap());
+}
+
+#[test]
+fn test_block_too_small() {
+    let fm = FileMgr::new_from_storage(
+        Box::new(MemoryStorage::new()),
+        40,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+
+    // no record could ever be started in a block this small
+    let err = LogMgr::new(Arc::new(fm), String::from("logfile"))
+        .err()
+        .unwrap();
+    assert_eq!(
+        Some(&LogMgrError::BlockTooSmall(40)),
+        err.downcast_ref::<LogMgrError>()
+    );
+}
+
+#[test]
+fn test_torn_tail() {
+    let storage = Arc::new(MemoryStorage::new());
+    let fm = Arc::new(
+        FileMgr::new_from_storage(
+            Box::new(Arc::clone(&storage)),
+            400,
+            FileMgrOptions::default(),
+        )
+        .unwrap(),
+    );
+    let open = || LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap();
+    // the last block has two copies on disk, the damage is done to every one holding records
+    let tamper = |f: &dyn Fn(&mut Page)| {
+        let mut p = fm.new_page();
+        for blk in &[
+            BlockId::new("logfile", 0),
+            BlockId::new(format!("logfile{}", SHADOW_SUFFIX), 0),
+        ] {
+            fm.read(blk, &mut p).unwrap();
+            if p.get_int(0).unwrap() < 400 {
+                f(&mut p);
+                fm.write(blk, &mut p).unwrap();
+            }
+        }
+    };
+
+    let lm = open();
+    create_records(&lm, 1, 5).unwrap();
+    lm.flush_from_lsn(5).unwrap();
+    drop(lm);
+
+    // the newest record was only partly written
+    tamper(&|p| {
+        let boundary = p.get_int(0).unwrap() as usize;
+        p.contents()[boundary + 20] ^= 0xff;
+    });
+
+    let lm = open();
+    assert_eq!(4, lm.latest_lsn().unwrap());
+    assert_eq!(4, lm.iterator().unwrap().count());
+    assert_eq!(5, lm.append(b"record5").unwrap());
+    lm.flush_from_lsn(5).unwrap();
+    drop(lm);
+
+    // a boundary that points into the middle of a record is not followed
+    tamper(&|p| {
+        let boundary = p.get_int(0).unwrap();
+        p.set_int(0, boundary + 3).unwrap();
+    });
+
+    let lm = open();
+    assert_eq!(5, lm.latest_lsn().unwrap());
+    let recs: Vec<Vec<u8>> = lm.iterator().unwrap().collect();
+    assert_eq!(5, recs.len());
+    assert_eq!(b"record5".to_vec(), recs[0]);
+}
+
+#[test]
+fn test_torn_log_block() {
+    let inner = Arc::new(MemoryStorage::new());
+    let open = || {
+        let storage = Arc::new(FaultStorage::new(Box::new(Arc::clone(&inner))));
+        let fm = FileMgr::new_from_storage(
+            Box::new(Arc::clone(&storage)),
+            400,
+            FileMgrOptions::default(),
+        )
+        .unwrap();
+        let lm = LogMgr::new(Arc::new(fm), String::from("logfile")).unwrap();
+        storage.clear();
+
+        (lm, storage)
+    };
+
+    // the rewrite of the block holding a flushed record tears. Flushes alternate between
+    // the block and its copy, one more flush before it makes the other one tear
+    for extra in 0..2 {
+        let (lm, storage) = open();
+        let first = lm.latest_lsn().unwrap() + 1;
+        lm.append(b"flushed").unwrap();
+        // a flush is a single block write
+        let writes = storage.writes();
+        lm.flush_from_lsn(first).unwrap();
+        assert_eq!(writes + 1, storage.writes());
+        assert_eq!(first, lm.lastsaved_lsn().unwrap());
+        for lsn in first + 1..=first + extra {
+            lm.append(b"filler").unwrap();
+            lm.flush_from_lsn(lsn).unwrap();
+        }
+
+        lm.append(b"torn").unwrap();
+        storage.inject(Fault::TornWrite {
+            nth: storage.writes() + 1,
+            bytes: 100,
+        });
+        assert!(lm.flush_from_lsn(first + extra + 1).is_err());
+        drop(lm);
+
+        let (lm, _) = open();
+        assert!(lm.latest_lsn().unwrap() >= first);
+        assert_eq!(Some(b"flushed".to_vec()), lm.record(first).unwrap());
+        let next = lm.latest_lsn().unwrap() + 1;
+        assert_eq!(next, lm.append(b"next").unwrap());
+        assert_eq!(Some(b"next".to_vec()), lm.record(next).unwrap());
+    }
+}
+
+// a disk whose syncs take a while, so commits pile up behind each other
+struct SlowSync(MemoryStorage);
+
+impl Storage for SlowSync {
+    fn read_block(&self, filename: &str, blknum: u64, buf: &mut [u8]) -> Result<usize> {
+        self.0.read_block(filename, blknum, buf)
+    }
+
+    fn write_block(&self, filename: &str, blknum: u64, buf: &[u8]) -> Result<()> {
+        self.0.write_block(filename, blknum, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.0.read_at(filename, offset, buf)
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<()> {
+        self.0.write_at(filename, offset, buf)
+    }
+
+    fn append_block(&self, filename: &str, buf: &[u8]) -> Result<u64> {
+        self.0.append_block(filename, buf)
+    }
+
+    fn length(&self, filename: &str, blocksize: u64) -> Result<u64> {
+        self.0.length(filename, blocksize)
+    }
+
+    fn truncate(&self, filename: &str, nblocks: u64, blocksize: u64) -> Result<()> {
+        self.0.truncate(filename, nblocks, blocksize)
+    }
+
+    fn delete(&self, filename: &str) -> Result<()> {
+        self.0.delete(filename)
+    }
+
+    fn sync(&self, filename: &str) -> Result<()> {
+        thread::sleep(Duration::from_millis(5));
+        self.0.sync(filename)
+    }
+
+    fn filenames(&self) -> Result<Vec<String>> {
+        self.0.filenames()
+    }
+}
+
+#[test]
+fn test_group_commit() {
+    let fm = Arc::new(
+        FileMgr::new_from_storage(
+            Box::new(SlowSync(MemoryStorage::new())),
+            4096,
+            FileMgrOptions::default(),
+        )
+        .unwrap(),
+    );
+    let lm = Arc::new(LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap());
+    fm.reset_io_stats();
+
+    // every thread commits its records one at a time
+    let handles: Vec<_> = (0..8)
+        .map(|t| {
+            let lm = Arc::clone(&lm);
+            thread::spawn(move || {
+                (0..10)
+                    .map(|i| {
+                        let lsn = lm.append(format!("tx{}-{}", t, i).as_bytes()).unwrap();
+                        lm.flush_from_lsn(lsn).unwrap();
+                        assert!(lm.lastsaved_lsn().unwrap() >= lsn);
+                        lsn
+                    })
+                    .collect::<Vec<u64>>()
+            })
+        })
+        .collect();
+    let mut lsns: Vec<u64> = handles
+        .into_iter()
+        .flat_map(|h| h.join().unwrap())
+        .collect();
+    lsns.sort_unstable();
+    assert_eq!((1..=80).collect::<Vec<u64>>(), lsns);
+
+    // the commits waiting behind a flush share the next one
+    let writes = fm.io_stats().file("logfile").writes;
+    assert!(writes < 40, "{} log writes for 80 commits", writes);
+    assert_eq!(80, lm.iterator().unwrap().count());
 }
 
-fn print_log_record(lm: &mut LogMgr, msg: String) -> Result<()> {
+fn print_log_record(lm: &LogMgr, msg: String) -> Result<()> {
     println!("{}", msg);
 
     for rec in lm.iterator()? {
@@ -31,7 +454,7 @@ fn print_log_record(lm: &mut LogMgr, msg: String) -> Result<()> {
     Ok(())
 }
 
-fn create_records(lm: &mut LogMgr, start: u64, end: u64) -> Result<()> {
+fn create_records(lm: &LogMgr, start: u64, end: u64) -> Result<()> {
     for i in start..end + 1 {
         let mut page = create_log_record(String::from("record") + &i.to_string(), i + 100)?;
         let lsn = lm.append(page.contents())?;
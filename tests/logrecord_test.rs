@@ -0,0 +1,120 @@
+use simple_db::blockid::BlockId;
+use simple_db::buffermanager::BufferMgr;
+use simple_db::checkpointrecord::CheckpointRecord;
+use simple_db::commitrecord::CommitRecord;
+use simple_db::filemanager::{FileMgr, FileMgrOptions};
+use simple_db::logmanager::LogMgr;
+use simple_db::logrecord::{self, LogRecord};
+use simple_db::memorystorage::MemoryStorage;
+use simple_db::rollbackrecord::RollbackRecord;
+use simple_db::setintrecord::SetIntRecord;
+use simple_db::setstringrecord::SetStringRecord;
+use simple_db::startrecord::StartRecord;
+
+use std::sync::Arc;
+
+fn new_managers() -> (Arc<LogMgr>, BufferMgr) {
+    let fm = FileMgr::new_from_storage(
+        Box::new(MemoryStorage::new()),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+    let fm = Arc::new(fm);
+    let lm = Arc::new(LogMgr::new(Arc::clone(&fm), String::from("logfile")).unwrap());
+    let bm = BufferMgr::new(fm, Arc::clone(&lm), 3);
+
+    (lm, bm)
+}
+
+#[test]
+fn test_logrecords() {
+    let (lm, _) = new_managers();
+    let blk = BlockId::new("testfile", 1);
+
+    StartRecord::write_to_log(Arc::clone(&lm), 1).unwrap();
+    SetIntRecord::write_to_log(Arc::clone(&lm), 1, blk.clone(), 80, 7).unwrap();
+    SetStringRecord::write_to_log(Arc::clone(&lm), 1, blk, 40, String::from("one")).unwrap();
+    CommitRecord::write_to_log(Arc::clone(&lm), 1).unwrap();
+    StartRecord::write_to_log(Arc::clone(&lm), 2).unwrap();
+    RollbackRecord::write_to_log(Arc::clone(&lm), 2).unwrap();
+    let lsn = CheckpointRecord::write_to_log(Arc::clone(&lm)).unwrap();
+    assert_eq!(7, lsn);
+
+    let records: Vec<LogRecord> = lm
+        .forward_iterator(1)
+        .unwrap()
+        .map(|(_, rec)| logrecord::create_logrecord(rec).unwrap())
+        .collect();
+    let described: Vec<(i32, i32, String)> = records
+        .iter()
+        .map(|r| (r.op(), r.tx_number(), r.to_string()))
+        .collect();
+    assert_eq!(
+        vec![
+            (logrecord::START, 1, String::from("<START 1>")),
+            (
+                logrecord::SETINT,
+                1,
+                String::from("<SETINT 1 [file testfile, block 1] 80 7>")
+            ),
+            (
+                logrecord::SETSTRING,
+                1,
+                String::from("<SETSTRING 1 [file testfile, block 1] 40 one>")
+            ),
+            (logrecord::COMMIT, 1, String::from("<COMMIT 1>")),
+            (logrecord::START, 2, String::from("<START 2>")),
+            (logrecord::ROLLBACK, 2, String::from("<ROLLBACK 2>")),
+            (logrecord::CHECKPOINT, -1, String::from("<CHECKPOINT>")),
+        ],
+        described
+    );
+}
+
+#[test]
+fn test_undo() {
+    let (lm, mut bm) = new_managers();
+    let blk = BlockId::new("testfile", 0);
+
+    // the old values are logged before the block is changed
+    let buff = bm.pin(&blk).unwrap();
+    {
+        let mut b = buff.borrow_mut();
+        let p = b.contents();
+        p.set_int(80, 7).unwrap();
+        p.set_string(40, "one").unwrap();
+    }
+    StartRecord::write_to_log(Arc::clone(&lm), 1).unwrap();
+    SetIntRecord::write_to_log(Arc::clone(&lm), 1, blk.clone(), 80, 7).unwrap();
+    let lsn =
+        SetStringRecord::write_to_log(Arc::clone(&lm), 1, blk.clone(), 40, String::from("one"))
+            .unwrap();
+    {
+        let mut b = buff.borrow_mut();
+        let p = b.contents();
+        p.set_int(80, 8).unwrap();
+        p.set_string(40, "two").unwrap();
+        b.set_modified(1, lsn as i64);
+    }
+    bm.unpin(buff).unwrap();
+
+    // roll the transaction back, newest record first
+    for rec in lm.iterator().unwrap() {
+        let rec = logrecord::create_logrecord(rec).unwrap();
+        if rec.op() == logrecord::START {
+            break;
+        }
+        rec.undo(&mut bm).unwrap();
+    }
+
+    let buff = bm.pin(&blk).unwrap();
+    {
+        let mut b = buff.borrow_mut();
+        assert_eq!(1, b.modifying_tx());
+        let p = b.contents();
+        assert_eq!(7, p.get_int(80).unwrap());
+        assert_eq!("one", p.get_string(40).unwrap());
+    }
+    bm.unpin(buff).unwrap();
+}
@@ -0,0 +1,197 @@
+use simple_db::blockid::BlockId;
+use simple_db::buffermanager::BufferMgr;
+use simple_db::filemanager::{FileMgr, FileMgrOptions};
+use simple_db::logmanager::LogMgr;
+use simple_db::memorystorage::MemoryStorage;
+use simple_db::overflow::{self, OverflowRef};
+use simple_db::page::Page;
+
+use std::sync::Arc;
+
+fn new_buffermgr(fm: &Arc<FileMgr>) -> BufferMgr {
+    let lm = LogMgr::new(Arc::clone(fm), String::from("overflowlog")).unwrap();
+
+    BufferMgr::new(Arc::clone(fm), Arc::new(lm), 3)
+}
+
+#[test]
+fn test_overflow() {
+    let fm = FileMgr::new_from_storage(
+        Box::new(MemoryStorage::new()),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+    let fm = Arc::new(fm);
+    let mut bm = new_buffermgr(&fm);
+
+    // far more than a block, and more blocks than the pool has buffers
+    let value: String = (0..300).map(|i| format!("{:>6}|", i)).collect();
+    assert!(value.len() > 5 * overflow::chunk_size(fm.blocksize()));
+
+    let record = fm.append("table").unwrap();
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    assert!(p.set_string(0, &value).is_err());
+
+    let r = overflow::write_chain(&fm, &mut bm, "table", value.as_bytes(), 1).unwrap();
+    let buff = bm.pin(&record).unwrap();
+    {
+        let mut b = buff.borrow_mut();
+        let p = b.contents();
+        p.set_string(0, "inline").unwrap();
+        r.set(p, 20).unwrap();
+        b.set_modified(1, -1);
+    }
+    bm.unpin(buff).unwrap();
+    bm.flush_all(1).unwrap();
+
+    // a fresh buffer pool finds the value from the reference on disk
+    let mut bm = new_buffermgr(&fm);
+    fm.read(&record, &mut p).unwrap();
+    assert_eq!(None, OverflowRef::get(&p, 0).unwrap());
+    let r = OverflowRef::get(&p, 20).unwrap().unwrap();
+    assert_eq!(value.len() as u64, r.len);
+
+    let read = overflow::read_chain(&fm, &mut bm, "table", &r).unwrap();
+    assert_eq!(value.as_bytes(), &read[..]);
+
+    // an empty value still gets its block
+    let r = overflow::write_chain(&fm, &mut bm, "table", b"", 2).unwrap();
+    assert!(overflow::read_chain(&fm, &mut bm, "table", &r)
+        .unwrap()
+        .is_empty());
+
+    // a reference into the middle of other data is caught
+    let bogus = OverflowRef {
+        first: record.number(),
+        len: 10_000,
+    };
+    assert!(overflow::read_chain(&fm, &mut bm, "table", &bogus).is_err());
+    // as is a length no file could hold, before anything is allocated for it
+    let bogus = OverflowRef {
+        first: record.number(),
+        len: -5i64 as u64,
+    };
+    assert!(overflow::read_chain(&fm, &mut bm, "table", &bogus).is_err());
+    assert!(overflow::free_chain(&fm, &mut bm, "table", &bogus).is_err());
+    // and leaves no buffer pinned
+    assert_eq!(3, bm.available());
+    assert!(bm.pin(&BlockId::new("table", 1)).is_ok());
+}
+
+#[test]
+fn test_free_chain() {
+    let fm = FileMgr::new_from_storage(
+        Box::new(MemoryStorage::new()),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+    let fm = Arc::new(fm);
+    let mut bm = new_buffermgr(&fm);
+
+    let old: Vec<u8> = (0..2000).map(|i| (i % 251) as u8).collect();
+    let r = overflow::write_chain(&fm, &mut bm, "table", &old, 1).unwrap();
+    bm.flush_all(1).unwrap();
+    let nblocks = fm.length("table").unwrap();
+
+    // the replaced value gives its blocks back, and the new one takes them.
+    // They come off the free list in any order, so the new chain may point backwards
+    overflow::free_chain(&fm, &mut bm, "table", &r).unwrap();
+    let new: Vec<u8> = old.iter().rev().copied().collect();
+    let r = overflow::write_chain(&fm, &mut bm, "table", &new, 2).unwrap();
+    assert_eq!(nblocks, fm.length("table").unwrap());
+    assert_eq!(
+        new,
+        overflow::read_chain(&fm, &mut bm, "table", &r).unwrap()
+    );
+    bm.flush_all(2).unwrap();
+
+    let mut bm = new_buffermgr(&fm);
+    assert_eq!(
+        new,
+        overflow::read_chain(&fm, &mut bm, "table", &r).unwrap()
+    );
+}
+
+#[test]
+fn test_backward_chain() {
+    let fm = FileMgr::new_from_storage(
+        Box::new(MemoryStorage::new()),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+    let fm = Arc::new(fm);
+    let mut bm = new_buffermgr(&fm);
+
+    // a block freed while the chain was written comes before the ones appended for it
+    let write_block = |blknum: u64, next: i64, chunk: &[u8]| {
+        let mut p = Page::new_from_size(fm.blocksize() as usize);
+        p.set_long(0, next).unwrap();
+        p.set_bytes(8, chunk).unwrap();
+        fm.write(&BlockId::new("table", blknum), &mut p).unwrap();
+    };
+    let chunk = vec![7; overflow::chunk_size(fm.blocksize())];
+    write_block(2, 0, &chunk);
+    write_block(0, 1, &chunk);
+    write_block(1, -1, b"end");
+
+    let r = OverflowRef {
+        first: 2,
+        len: 2 * chunk.len() as u64 + 3,
+    };
+    let value = overflow::read_chain(&fm, &mut bm, "table", &r).unwrap();
+    assert_eq!([&chunk[..], &chunk[..], b"end"].concat(), value);
+
+    // but a chain that comes back to a block it passed is broken
+    write_block(1, 2, b"end");
+    let mut bm = new_buffermgr(&fm);
+    let r = OverflowRef {
+        first: 2,
+        len: 3 * chunk.len() as u64,
+    };
+    assert!(overflow::read_chain(&fm, &mut bm, "table", &r).is_err());
+    assert!(overflow::free_chain(&fm, &mut bm, "table", &r).is_err());
+}
+
+#[test]
+fn test_reuse_freed_block() {
+    let fm = FileMgr::new_from_storage(
+        Box::new(MemoryStorage::new()),
+        400,
+        FileMgrOptions::default(),
+    )
+    .unwrap();
+    let fm = Arc::new(fm);
+    let mut bm = new_buffermgr(&fm);
+
+    // still in the pool, and changed since it was last flushed
+    let r = overflow::write_chain(&fm, &mut bm, "table", b"hello", 1).unwrap();
+    assert_eq!(
+        b"hello",
+        &overflow::read_chain(&fm, &mut bm, "table", &r).unwrap()[..]
+    );
+
+    let buff = bm.pin(&BlockId::new("table", r.first)).unwrap();
+    assert!(overflow::free_chain(&fm, &mut bm, "table", &r).is_err());
+    bm.unpin(buff).unwrap();
+    overflow::free_chain(&fm, &mut bm, "table", &r).unwrap();
+
+    // the block comes back all zeroes, in the pool and on disk
+    let blk = fm.append("table").unwrap();
+    assert_eq!(r.first, blk.number());
+    let buff = bm.pin(&blk).unwrap();
+    assert!(buff
+        .borrow_mut()
+        .contents()
+        .contents()
+        .iter()
+        .all(|b| *b == 0));
+    bm.unpin(buff).unwrap();
+    bm.flush_all(1).unwrap();
+
+    let mut p = Page::new_from_size(fm.blocksize() as usize);
+    fm.read(&blk, &mut p).unwrap();
+    assert!(p.contents().iter().all(|b| *b == 0));
+}
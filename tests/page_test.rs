@@ -1,5 +1,7 @@
 use simple_db::page::Page;
 
+use std::time::{Duration, UNIX_EPOCH};
+
 #[test]
 fn test_int() {
     let mut page = Page::new_from_size(20);
@@ -18,4 +20,70 @@ fn test_string() {
     page.set_string(offset, value).unwrap();
 
     assert_eq!("hello", page.get_string(offset).unwrap());
+    assert_eq!("hello", page.get_str(offset).unwrap());
+}
+
+#[test]
+fn test_numbers() {
+    let mut page = Page::new_from_size(40);
+
+    let mut offset = page.set_short(0, -12345).unwrap();
+    assert_eq!(Page::short_length(), offset);
+    offset = page.set_long(offset, i64::MIN + 1).unwrap();
+    let dpos = offset;
+    offset = page.set_double(offset, -1.5e-300).unwrap();
+    let bpos = offset;
+    offset = page.set_bool(offset, true).unwrap();
+    assert_eq!(
+        Page::short_length() + Page::long_length() + Page::double_length() + Page::bool_length(),
+        offset
+    );
+
+    assert_eq!(-12345, page.get_short(0).unwrap());
+    assert_eq!(i64::MIN + 1, page.get_long(Page::short_length()).unwrap());
+    assert_eq!(-1.5e-300, page.get_double(dpos).unwrap());
+    assert!(page.get_bool(bpos).unwrap());
+    page.set_bool(bpos, false).unwrap();
+    assert!(!page.get_bool(bpos).unwrap());
+}
+
+#[test]
+fn test_bytes_and_timestamps() {
+    let mut page = Page::new_from_size(40);
+
+    // empty values are fine
+    let offset = page.set_bytes(0, &[]).unwrap();
+    assert_eq!(Page::bytes_length(0), offset);
+    assert!(page.get_bytes(0).unwrap().is_empty());
+
+    let blob = [0u8, 1, 2, 255];
+    let tpos = page.set_bytes(offset, &blob).unwrap();
+    assert_eq!(&blob[..], page.get_bytes(offset).unwrap());
+
+    let t = UNIX_EPOCH + Duration::from_micros(1_600_000_000_123_456);
+    page.set_timestamp(tpos, t).unwrap();
+    assert_eq!(t, page.get_timestamp(tpos).unwrap());
+
+    let before_epoch = UNIX_EPOCH - Duration::from_secs(86400);
+    page.set_timestamp(tpos, before_epoch).unwrap();
+    assert_eq!(before_epoch, page.get_timestamp(tpos).unwrap());
+    assert_eq!(20, tpos + Page::timestamp_length());
+}
+
+#[test]
+fn test_bounds() {
+    let mut page = Page::new_from_size(8);
+
+    assert!(page.set_long(0, 1).is_ok());
+    assert!(page.set_long(1, 1).is_err());
+    assert!(page.get_int(6).is_err());
+    assert!(page.get_short(usize::MAX).is_err());
+    assert!(page.set_bytes(4, &[1]).is_err());
+    assert!(page.set_bytes(4, &[]).is_ok());
+
+    // a length that runs past the end of the page
+    page.set_int(0, 100).unwrap();
+    assert!(page.get_bytes(0).is_err());
+    page.set_int(0, -1).unwrap();
+    assert!(page.get_bytes(0).is_err());
 }
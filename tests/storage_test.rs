@@ -0,0 +1,27 @@
+use simple_db::memorystorage::MemoryStorage;
+use simple_db::storage::Storage;
+
+#[test]
+fn test_memory_storage() {
+    let storage = MemoryStorage::new();
+    let mut buf = [0; 4];
+
+    assert_eq!(0, storage.length("memfile", 4).unwrap());
+    assert_eq!(0, storage.read_block("memfile", 0, &mut buf).unwrap());
+
+    // writing past the end leaves a hole of zeroes
+    storage.write_block("memfile", 1, &[1, 2, 3, 4]).unwrap();
+    assert_eq!(2, storage.length("memfile", 4).unwrap());
+    assert_eq!(4, storage.read_block("memfile", 0, &mut buf).unwrap());
+    assert_eq!([0, 0, 0, 0], buf);
+    assert_eq!(4, storage.read_block("memfile", 1, &mut buf).unwrap());
+    assert_eq!([1, 2, 3, 4], buf);
+
+    assert_eq!(2, storage.append_block("memfile", &[5, 6, 7, 8]).unwrap());
+    assert_eq!(3, storage.length("memfile", 4).unwrap());
+    assert_eq!(vec!["memfile".to_string()], storage.filenames().unwrap());
+
+    storage.delete("memfile").unwrap();
+    assert_eq!(0, storage.length("memfile", 4).unwrap());
+    assert!(storage.filenames().unwrap().is_empty());
+}
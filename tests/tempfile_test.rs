@@ -0,0 +1,43 @@
+use simple_db::filemanager::{FileMgr, FileMgrOptions};
+use simple_db::memorystorage::MemoryStorage;
+use simple_db::storage::Storage;
+use simple_db::tempfile::TempFile;
+
+use std::mem;
+use std::sync::Arc;
+
+#[test]
+fn test_tempfile() {
+    let storage = Arc::new(MemoryStorage::new());
+    let fm = Arc::new(
+        FileMgr::new_from_storage(
+            Box::new(Arc::clone(&storage)),
+            400,
+            FileMgrOptions::default(),
+        )
+        .unwrap(),
+    );
+
+    let t1 = TempFile::new(Arc::clone(&fm)).unwrap();
+    let t2 = TempFile::new(Arc::clone(&fm)).unwrap();
+    assert_ne!(t1.filename(), t2.filename());
+    assert!(t1.filename().starts_with("temp"));
+
+    assert_eq!(t1.block(0), fm.append(t1.filename()).unwrap());
+    fm.append(t2.filename()).unwrap();
+    assert_eq!(2, fm.temp_filenames().len());
+
+    let name1 = t1.filename().to_string();
+    drop(t1);
+    assert_eq!(0, fm.length(&name1).unwrap());
+    assert_eq!(vec![t2.filename().to_string()], fm.temp_filenames());
+
+    // a temporary file that outlives its process is removed at the next startup
+    let name2 = t2.filename().to_string();
+    mem::forget(t2);
+    assert!(storage.filenames().unwrap().contains(&name2));
+
+    let fm = FileMgr::new_from_storage(Box::new(storage), 400, FileMgrOptions::default()).unwrap();
+    assert_eq!(0, fm.length(&name2).unwrap());
+    assert!(fm.temp_filenames().is_empty());
+}